@@ -5,7 +5,12 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use classicube_sys::{PackedCol, PackedCol_Make};
+use classicube_sys::PackedCol;
+
+use self::color::color;
+
+#[path = "src/color.rs"]
+mod color;
 
 const IMAGE_DIR: &str = "textures";
 
@@ -54,19 +59,6 @@ fn get_pixels(path: PathBuf) -> (u32, u32, Vec<PackedCol>) {
 
     assert_eq!(info.bit_depth, png::BitDepth::Eight);
 
-    // TODO fix PackedCol_Make on linux
-    fn color(r: u8, g: u8, b: u8, a: u8) -> PackedCol {
-        #[cfg(not(target_os = "linux"))]
-        {
-            PackedCol_Make(r, g, b, a)
-        }
-
-        #[cfg(target_os = "linux")]
-        {
-            PackedCol_Make(b, g, r, a)
-        }
-    }
-
     (
         info.width,
         info.height,
@@ -15,6 +15,9 @@
             "^sounds(/.*)?$"
             "^src(/.*)?$"
             "^textures(/.*)?$"
+            "^toolgun-inspect(/.*)?$"
+            "^toolgun-mock-server(/.*)?$"
+            "^toolgun-protocol(/.*)?$"
           ];
 
           args = {
@@ -0,0 +1,16 @@
+//! Shared with build.rs, which includes this file directly.
+
+use classicube_sys::{PackedCol, PackedCol_Make};
+
+// TODO fix PackedCol_Make on linux
+pub fn color(r: u8, g: u8, b: u8, a: u8) -> PackedCol {
+    #[cfg(not(target_os = "linux"))]
+    {
+        PackedCol_Make(r, g, b, a)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        PackedCol_Make(b, g, r, a)
+    }
+}
@@ -1,3 +1,4 @@
+pub mod color;
 pub mod plugin;
 pub mod textures;
 
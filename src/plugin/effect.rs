@@ -0,0 +1,54 @@
+/// What happened at a block, picks the beam's color and the sound's pitch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Effect {
+    /// the plain toolgun beam, also for shots
+    #[default]
+    Place,
+    /// block to air
+    Break,
+    /// block to another block
+    Replace,
+    /// the server undid one of ours
+    Denied,
+}
+
+impl Effect {
+    /// `None` if nothing visible changed
+    pub fn from_blocks(old_block: u16, new_block: u16) -> Option<Self> {
+        match (old_block, new_block) {
+            _ if old_block == new_block => None,
+            (0, _) => Some(Self::Place),
+            (_, 0) => Some(Self::Break),
+            _ => Some(Self::Replace),
+        }
+    }
+
+    /// rgb multiplied into the beam texture
+    pub fn tint(&self) -> (u8, u8, u8) {
+        match self {
+            Self::Place => (255, 255, 255),
+            Self::Break => (255, 150, 40),
+            Self::Replace => (80, 200, 255),
+            Self::Denied => (255, 40, 40),
+        }
+    }
+
+    /// playback speed of the toolgun sound, lower for breaking, a fizzle when denied
+    pub fn sound_speed(&self) -> f32 {
+        match self {
+            Self::Place => 1.0,
+            Self::Break => 0.7,
+            Self::Replace => 1.3,
+            Self::Denied => 0.45,
+        }
+    }
+}
+
+#[test]
+fn test_from_blocks() {
+    assert_eq!(Effect::from_blocks(0, 1), Some(Effect::Place));
+    assert_eq!(Effect::from_blocks(1, 0), Some(Effect::Break));
+    assert_eq!(Effect::from_blocks(1, 2), Some(Effect::Replace));
+    assert_eq!(Effect::from_blocks(0, 0), None);
+    assert_eq!(Effect::from_blocks(3, 3), None);
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::visibility::View;
+
+/// how far a player can place blocks, generous for servers that raise it
+pub const REACH: f32 = 16.0;
+/// how far off a player's line of sight a block may be and still be theirs
+pub const RAY_RADIUS: f32 = 1.5;
+/// from the feet, the default humanoid model's
+pub const EYE_HEIGHT: f32 = 26.0 / 16.0;
+/// how long the server has to answer one of our own changes
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Who most likely placed a block at `pos` that nobody claimed: the player
+/// looking closest to it within reach, if any.
+pub fn attribute(
+    pos: (i32, i32, i32),
+    players: impl IntoIterator<Item = (u8, View)>,
+) -> Option<u8> {
+    players
+        .into_iter()
+        .filter_map(|(player_id, view)| {
+            let (along, off) = view.ray_distance(pos)?;
+            (along <= REACH && off <= RAY_RADIUS).then_some((player_id, off))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(player_id, _)| player_id)
+}
+
+/// What the server sent about one of our own changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    /// the same block back
+    Echo,
+    /// the block that was there before, it didn't let us
+    Revert,
+}
+
+/// A change we made ourselves, and when.
+struct Placement {
+    old_block: u16,
+    new_block: u16,
+    time: Instant,
+}
+
+/// Blocks we changed ourselves, so the server sending them back isn't mistaken
+/// for someone else building, and a denied one can be told apart.
+#[derive(Default)]
+pub struct RecentPlacements {
+    placements: HashMap<(i32, i32, i32), Placement>,
+}
+
+impl RecentPlacements {
+    pub fn record(&mut self, pos: (i32, i32, i32), old_block: u16, new_block: u16, now: Instant) {
+        self.placements.insert(
+            pos,
+            Placement {
+                old_block,
+                new_block,
+                time: now,
+            },
+        );
+    }
+
+    /// Whether `block` at `pos` answers one of ours.
+    ///
+    /// Only the first change there after ours can, assuming the server answers
+    /// before anyone else gets to that block. Servers that don't echo ours only
+    /// answer denied ones, so on those someone else putting the old block back
+    /// first still looks like a denial.
+    pub fn take_reply(&mut self, pos: (i32, i32, i32), block: u16, now: Instant) -> Option<Reply> {
+        let placement = self.placements.remove(&pos)?;
+        if now.saturating_duration_since(placement.time) >= REPLY_TIMEOUT {
+            return None;
+        }
+        if block == placement.new_block {
+            Some(Reply::Echo)
+        } else if block == placement.old_block {
+            Some(Reply::Revert)
+        } else {
+            None
+        }
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        self.placements
+            .retain(|_, placement| now.saturating_duration_since(placement.time) < REPLY_TIMEOUT);
+    }
+
+    pub fn clear(&mut self) {
+        self.placements.clear();
+    }
+}
+
+#[test]
+fn test_attribute() {
+    // looking north, -z
+    let view = |x, z| View {
+        eye: (x, 1.5, z),
+        yaw: 0.0,
+        pitch: 0.0,
+    };
+
+    assert_eq!(attribute((0, 1, -5), [(1, view(0.5, 0.5))]), Some(1));
+    // too far off, too far away, or behind
+    assert_eq!(attribute((0, 1, -5), [(1, view(5.5, 0.5))]), None);
+    assert_eq!(attribute((0, 1, -50), [(1, view(0.5, 0.5))]), None);
+    assert_eq!(attribute((0, 1, 5), [(1, view(0.5, 0.5))]), None);
+
+    // whoever is looking straighter at it
+    assert_eq!(
+        attribute((0, 1, -5), [(1, view(1.5, 0.5)), (2, view(0.5, 2.5))]),
+        Some(2)
+    );
+    assert_eq!(attribute((0, 1, -5), []), None);
+}
+
+#[test]
+fn test_recent_placements() {
+    let now = Instant::now();
+    let mut placements = RecentPlacements::default();
+    placements.record((1, 2, 3), 0, 5, now);
+
+    assert_eq!(placements.take_reply((1, 2, 4), 5, now), None);
+    assert_eq!(placements.take_reply((1, 2, 3), 5, now), Some(Reply::Echo));
+    assert_eq!(placements.take_reply((1, 2, 3), 5, now), None);
+
+    // someone else got there first, whatever comes after isn't an answer
+    placements.record((1, 2, 3), 0, 5, now);
+    assert_eq!(placements.take_reply((1, 2, 3), 6, now), None);
+    assert_eq!(placements.take_reply((1, 2, 3), 0, now), None);
+
+    // put back to air
+    placements.record((1, 2, 3), 0, 5, now);
+    assert_eq!(
+        placements.take_reply((1, 2, 3), 0, now),
+        Some(Reply::Revert)
+    );
+
+    placements.record((1, 2, 3), 0, 5, now);
+    assert_eq!(
+        placements.take_reply((1, 2, 3), 5, now + REPLY_TIMEOUT),
+        None
+    );
+    placements.expire(now + REPLY_TIMEOUT);
+    assert!(placements.placements.is_empty());
+}
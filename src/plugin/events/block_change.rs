@@ -0,0 +1,158 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{Result, bail};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+
+/// blocks in every `BULK_BLOCK_UPDATE`, the count says how many are used
+pub const BULK_BLOCK_COUNT: usize = 256;
+
+/// One block set by the server, whether it came alone or in bulk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockChange {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub block: u16,
+}
+
+impl BlockChange {
+    pub fn pos(&self) -> (i32, i32, i32) {
+        (self.x, self.y, self.z)
+    }
+}
+
+/// `extended_blocks` if CPE ExtendedBlocks made block ids 2 bytes
+fn read_block(data_stream: &mut impl Read, extended_blocks: bool) -> Result<u16> {
+    Ok(if extended_blocks {
+        data_stream.read_u16::<NetworkEndian>()?
+    } else {
+        data_stream.read_u8()?.into()
+    })
+}
+
+/// `SET_BLOCK` without the opcode
+pub fn decode_set_block(data: &[u8], extended_blocks: bool) -> Result<BlockChange> {
+    let mut data_stream = Cursor::new(data);
+    Ok(BlockChange {
+        x: data_stream.read_u16::<NetworkEndian>()?.into(),
+        y: data_stream.read_u16::<NetworkEndian>()?.into(),
+        z: data_stream.read_u16::<NetworkEndian>()?.into(),
+        block: read_block(&mut data_stream, extended_blocks)?,
+    })
+}
+
+/// `SET_BLOCK` without the opcode, for handing a change to the original handler
+pub fn encode_set_block(change: &BlockChange, extended_blocks: bool) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    data.write_u16::<NetworkEndian>(change.x.try_into()?)?;
+    data.write_u16::<NetworkEndian>(change.y.try_into()?)?;
+    data.write_u16::<NetworkEndian>(change.z.try_into()?)?;
+    if extended_blocks {
+        data.write_u16::<NetworkEndian>(change.block)?;
+    } else {
+        data.write_u8(change.block.try_into()?)?;
+    }
+    Ok(data)
+}
+
+/// `BULK_BLOCK_UPDATE` without the opcode: count - 1, `BULK_BLOCK_COUNT` indices,
+/// as many block ids, and with ExtendedBlocks 2 more bits per block.
+///
+/// Indices outside of a `size` map are skipped, like the game does.
+pub fn decode_bulk_block_update(
+    data: &[u8],
+    extended_blocks: bool,
+    size: (i32, i32, i32),
+) -> Result<Vec<BlockChange>> {
+    let (width, height, length) = size;
+    if width <= 0 || height <= 0 || length <= 0 {
+        bail!("no map");
+    }
+
+    let mut data_stream = Cursor::new(data);
+    let count = usize::from(data_stream.read_u8()?) + 1;
+
+    let mut indices = [0; BULK_BLOCK_COUNT];
+    data_stream.read_i32_into::<NetworkEndian>(&mut indices)?;
+    let mut low = [0; BULK_BLOCK_COUNT];
+    data_stream.read_exact(&mut low)?;
+    let mut high = [0; BULK_BLOCK_COUNT / 4];
+    if extended_blocks {
+        data_stream.read_exact(&mut high)?;
+    }
+
+    let volume = i64::from(width) * i64::from(height) * i64::from(length);
+    Ok((0..count)
+        .filter(|&i| (0..volume).contains(&i64::from(indices[i])))
+        .map(|i| {
+            let index = indices[i];
+            let high_bits = (high[i / 4] >> ((i % 4) * 2)) & 0b11;
+            BlockChange {
+                x: index % width,
+                y: index / (width * length),
+                z: (index / width) % length,
+                block: u16::from(low[i]) | (u16::from(high_bits) << 8),
+            }
+        })
+        .collect())
+}
+
+#[test]
+fn test_set_block() {
+    let change = BlockChange {
+        x: 1,
+        y: 300,
+        z: 65535,
+        block: 20,
+    };
+    for extended_blocks in [false, true] {
+        let data = encode_set_block(&change, extended_blocks).unwrap();
+        assert_eq!(data.len(), if extended_blocks { 8 } else { 7 });
+        assert_eq!(decode_set_block(&data, extended_blocks).unwrap(), change);
+    }
+
+    let change = BlockChange {
+        block: 700,
+        ..change
+    };
+    assert!(encode_set_block(&change, false).is_err());
+    assert_eq!(
+        decode_set_block(&encode_set_block(&change, true).unwrap(), true).unwrap(),
+        change
+    );
+}
+
+#[test]
+fn test_bulk_block_update() {
+    let size = (4, 8, 2);
+    let mut data = vec![3];
+    // 2nd is out of the map
+    for index in [0, 1000, 4 * 2 * 5 + 4 + 3, 9] {
+        data.extend(i32::to_be_bytes(index));
+    }
+    data.resize(1 + BULK_BLOCK_COUNT * 4, 0);
+    data.extend([1, 2, 3, 4]);
+    data.resize(1 + BULK_BLOCK_COUNT * 5, 0);
+
+    let blocks = |changes: Vec<BlockChange>| {
+        changes
+            .iter()
+            .map(|change| (change.pos(), change.block))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        blocks(decode_bulk_block_update(&data, false, size).unwrap()),
+        [((0, 0, 0), 1), ((3, 5, 1), 3), ((1, 1, 0), 4)]
+    );
+
+    // high bits of the 1st and 4th block
+    data.push(0b1100_0001);
+    data.resize(1 + BULK_BLOCK_COUNT * 5 + BULK_BLOCK_COUNT / 4, 0);
+    assert_eq!(
+        blocks(decode_bulk_block_update(&data, true, size).unwrap()),
+        [((0, 0, 0), 257), ((3, 5, 1), 3), ((1, 1, 0), 772)]
+    );
+
+    assert!(decode_bulk_block_update(&data[..100], false, size).is_err());
+    assert!(decode_bulk_block_update(&data, false, (0, 0, 0)).is_err());
+}
@@ -0,0 +1,289 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Error, Result, bail};
+
+/// side of the cube that changes nobody claimed are grouped by
+pub const REGION_SIZE: i32 = 16;
+/// how long a toolgun shot at a block claims changes to that block
+pub const CLAIM_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whose turn a queued change waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueueKey {
+    Player(u8),
+    /// `REGION_SIZE` cube, for changes we can't pin on anyone
+    Region(i32, i32, i32),
+}
+
+impl QueueKey {
+    pub fn region(x: i32, y: i32, z: i32) -> Self {
+        Self::Region(
+            x.div_euclid(REGION_SIZE),
+            y.div_euclid(REGION_SIZE),
+            z.div_euclid(REGION_SIZE),
+        )
+    }
+}
+
+/// Which of a key's queued changes goes next, so big builds visibly construct
+/// themselves instead of appearing in memory order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Arrival,
+    /// lowest layer first
+    BottomUp,
+    /// closest to whoever built it first
+    Nearest,
+    /// a diagonal sweep across the build
+    Wave,
+}
+
+impl FromStr for Order {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "arrival" => Self::Arrival,
+            "bottom_up" => Self::BottomUp,
+            "nearest" => Self::Nearest,
+            "wave" => Self::Wave,
+            other => bail!("unknown order {:?}", other),
+        })
+    }
+}
+
+impl Order {
+    /// lower goes first, ties in arrival order; `origin` is the builder's position
+    pub fn rank(&self, pos: (i32, i32, i32), origin: Option<(f32, f32, f32)>) -> i64 {
+        let (x, y, z) = pos;
+        match self {
+            Self::Arrival => 0,
+            Self::BottomUp => y.into(),
+            Self::Nearest => origin.map_or(0, |(ox, oy, oz)| {
+                let dx = x as f32 + 0.5 - ox;
+                let dy = y as f32 + 0.5 - oy;
+                let dz = z as f32 + 0.5 - oz;
+                (dx * dx + dy * dy + dz * dz) as i64
+            }),
+            Self::Wave => i64::from(x) + i64::from(z),
+        }
+    }
+}
+
+/// rank, arrival, position; lowest first
+type Queue = BinaryHeap<Reverse<(i64, u64, (i32, i32, i32))>>;
+
+/// One queue per `QueueKey`, drained round-robin so a huge paste by one player
+/// doesn't hold up everyone else's single blocks.
+///
+/// Queues hold positions, a block changed again while still queued keeps its
+/// place in line but only the latest change is returned.
+pub struct BlockQueue<T> {
+    queues: HashMap<QueueKey, Queue>,
+    /// keys with something queued, next one to drain at the front
+    order: VecDeque<QueueKey>,
+    latest: HashMap<(i32, i32, i32), T>,
+    next_sequence: u64,
+}
+
+impl<T> Default for BlockQueue<T> {
+    fn default() -> Self {
+        Self {
+            queues: HashMap::new(),
+            order: VecDeque::new(),
+            latest: HashMap::new(),
+            next_sequence: 0,
+        }
+    }
+}
+
+impl<T> BlockQueue<T> {
+    /// Returns the change to `pos` this one superseded, if any.
+    ///
+    /// `rank` from `Order::rank`, 0 for arrival order.
+    pub fn push(&mut self, key: QueueKey, pos: (i32, i32, i32), rank: i64, item: T) -> Option<T> {
+        if let Some(superseded) = self.latest.insert(pos, item) {
+            return Some(superseded);
+        }
+        let queue = self.queues.entry(key).or_default();
+        if queue.is_empty() {
+            self.order.push_back(key);
+        }
+        queue.push(Reverse((rank, self.next_sequence, pos)));
+        self.next_sequence += 1;
+        None
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let key = self.order.pop_front()?;
+        let queue = self.queues.get_mut(&key)?;
+        let Reverse((_, _, pos)) = queue.pop()?;
+        if queue.is_empty() {
+            self.queues.remove(&key);
+        } else {
+            self.order.push_back(key);
+        }
+        self.latest.remove(&pos)
+    }
+
+    pub fn contains(&self, pos: (i32, i32, i32)) -> bool {
+        self.latest.contains_key(&pos)
+    }
+
+    /// the change still queued for `pos`, keeping its place in line
+    pub fn get_mut(&mut self, pos: (i32, i32, i32)) -> Option<&mut T> {
+        self.latest.get_mut(&pos)
+    }
+
+    /// distinct blocks across all keys
+    pub fn len(&self) -> usize {
+        self.latest.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.queues.clear();
+        self.order.clear();
+        self.latest.clear();
+        self.next_sequence = 0;
+    }
+}
+
+/// Remembers which player recently shot at which block, the server relays their
+/// toolgun packets around the time it sends the block change.
+#[derive(Default)]
+pub struct Claims {
+    claims: HashMap<(i32, i32, i32), (u8, Instant)>,
+}
+
+impl Claims {
+    pub fn claim(&mut self, player_id: u8, pos: (i32, i32, i32), now: Instant) {
+        self.claims.insert(pos, (player_id, now));
+    }
+
+    pub fn key_for(&self, pos: (i32, i32, i32), now: Instant) -> QueueKey {
+        match self.claims.get(&pos) {
+            Some(&(player_id, time)) if now.saturating_duration_since(time) < CLAIM_TIMEOUT => {
+                QueueKey::Player(player_id)
+            }
+            _ => QueueKey::region(pos.0, pos.1, pos.2),
+        }
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        self.claims
+            .retain(|_, (_, time)| now.saturating_duration_since(*time) < CLAIM_TIMEOUT);
+    }
+
+    pub fn clear(&mut self) {
+        self.claims.clear();
+    }
+}
+
+#[test]
+fn test_round_robin() {
+    let mut queue = BlockQueue::default();
+    let paster = QueueKey::Player(1);
+    for i in 0..100 {
+        queue.push(paster, (i, 0, 0), 0, i);
+    }
+    queue.push(QueueKey::Player(2), (0, 1, 0), 0, 1000);
+    queue.push(QueueKey::region(-1, 0, 40), (-1, 0, 40), 0, 2000);
+    assert_eq!(queue.len(), 102);
+
+    // the single blocks don't wait for the paste
+    assert_eq!(queue.pop(), Some(0));
+    assert_eq!(queue.pop(), Some(1000));
+    assert_eq!(queue.pop(), Some(2000));
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.pop(), Some(2));
+
+    queue.push(QueueKey::Player(2), (0, 1, 0), 0, 1001);
+    assert_eq!(queue.pop(), Some(3));
+    assert_eq!(queue.pop(), Some(1001));
+    assert_eq!(queue.len(), 96);
+
+    queue.clear();
+    assert!(queue.is_empty());
+    assert_eq!(queue.pop(), None);
+}
+
+#[test]
+fn test_coalesce() {
+    let mut queue = BlockQueue::default();
+    let key = QueueKey::Player(1);
+    queue.push(key, (1, 1, 1), 0, "placed");
+    queue.push(key, (2, 2, 2), 0, "other");
+    assert_eq!(queue.push(key, (1, 1, 1), 0, "removed"), Some("placed"));
+    assert_eq!(
+        queue.push(QueueKey::Player(2), (1, 1, 1), 0, "replaced"),
+        Some("removed")
+    );
+    assert_eq!(queue.len(), 2);
+
+    // first in line, with the final state
+    assert_eq!(queue.pop(), Some("replaced"));
+    assert_eq!(queue.pop(), Some("other"));
+    assert_eq!(queue.pop(), None);
+
+    // popped blocks queue again
+    assert!(!queue.contains((1, 1, 1)));
+    queue.push(key, (1, 1, 1), 0, "again");
+    assert!(queue.contains((1, 1, 1)));
+    *queue.get_mut((1, 1, 1)).unwrap() = "shot";
+    assert_eq!(queue.get_mut((2, 2, 2)), None);
+    assert_eq!(queue.pop(), Some("shot"));
+}
+
+#[test]
+fn test_order() {
+    let positions = [(0, 2, 0), (5, 0, 5), (1, 0, 0), (0, 1, 1)];
+    let drain = |order: Order, origin| {
+        let mut queue = BlockQueue::default();
+        for pos in positions {
+            queue.push(QueueKey::Player(1), pos, order.rank(pos, origin), pos);
+        }
+        std::iter::from_fn(|| queue.pop()).collect::<Vec<_>>()
+    };
+
+    assert_eq!(drain(Order::Arrival, None), positions);
+    assert_eq!(
+        drain(Order::BottomUp, None),
+        [(5, 0, 5), (1, 0, 0), (0, 1, 1), (0, 2, 0)]
+    );
+    assert_eq!(
+        drain(Order::Nearest, Some((0.0, 3.0, 0.0))),
+        [(0, 2, 0), (0, 1, 1), (1, 0, 0), (5, 0, 5)]
+    );
+    // without a builder to measure from
+    assert_eq!(drain(Order::Nearest, None), positions);
+    assert_eq!(
+        drain(Order::Wave, None),
+        [(0, 2, 0), (1, 0, 0), (0, 1, 1), (5, 0, 5)]
+    );
+
+    assert_eq!("bottom_up".parse::<Order>().unwrap(), Order::BottomUp);
+    assert!("random".parse::<Order>().is_err());
+}
+
+#[test]
+fn test_claims() {
+    let now = Instant::now();
+    let mut claims = Claims::default();
+    claims.claim(3, (1, 2, 3), now);
+    assert_eq!(claims.key_for((1, 2, 3), now), QueueKey::Player(3));
+    assert_eq!(claims.key_for((-1, 2, 3), now), QueueKey::Region(-1, 0, 0));
+
+    let later = now + CLAIM_TIMEOUT;
+    assert_eq!(claims.key_for((1, 2, 3), later), QueueKey::Region(0, 0, 0));
+    claims.expire(later);
+    assert!(claims.claims.is_empty());
+}
@@ -1,5 +1,10 @@
+pub mod attribution;
+pub mod block_change;
+pub mod block_queue;
 pub mod local_blocks;
 pub mod other_blocks;
+pub mod pacing;
+pub mod visibility;
 
 use self::{local_blocks::LocalBlocksModule, other_blocks::OtherBlocksModule};
 use crate::plugin::module::Module;
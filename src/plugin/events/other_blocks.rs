@@ -1,24 +1,61 @@
 use std::{
     cell::{Cell, RefCell},
-    collections::VecDeque,
+    collections::HashMap,
     os::raw::c_int,
+    rc::Rc,
     slice,
-    time::{Duration, Instant},
+    time::Instant,
 };
 
-use classicube_helpers::{entities::ENTITY_SELF_ID, tick::TickEventHandler};
+use classicube_helpers::{
+    entities::ENTITY_SELF_ID, events::user::BlockChangedEventHandler, tick::TickEventHandler,
+};
 use classicube_sys::{
-    BlockID, IVec3, Lighting, Net_Handler, OPCODE__OPCODE_BULK_BLOCK_UPDATE,
-    OPCODE__OPCODE_SET_BLOCK, Protocol, cc_uint8,
+    BlockID, Camera, Entities, Lighting, Net_Handler, OPCODE__OPCODE_BULK_BLOCK_UPDATE,
+    OPCODE__OPCODE_SET_BLOCK, Protocol, World, cc_uint8,
 };
-use tracing::debug;
+use toolgun_protocol::{BlockPos, Packet, Target};
+use tracing::{debug, error};
 
+use super::{
+    attribution::{EYE_HEIGHT, RecentPlacements, Reply, attribute},
+    block_change::{BlockChange, decode_bulk_block_update, decode_set_block, encode_set_block},
+    block_queue::{BlockQueue, CLAIM_TIMEOUT, Claims, Order, QueueKey},
+    pacing::{self, Budget, MaxLag, Pacing},
+    visibility::{VIEW_HALF_ANGLE, View},
+};
 use crate::plugin::{
+    effect::Effect,
+    hook::{install_hook, net_handlers_eq, uninstall_hook},
     is_plugin_active,
     module::Module,
-    networking::packet::{Packet, handle_packet},
+    networking::{handle_effect, handle_impact},
+    render::{
+        block_animation::{BlockAnimation, GROW_DURATION, animates},
+        create_laser, dissolve_block, grow_block, spawn_debris,
+    },
 };
 
+/// including the opcode, one more with ExtendedBlocks
+const SET_BLOCK_SIZE: usize = 8;
+
+/// Who a change gets its beam from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Player(u8),
+    /// a player whose relayed shot already drew the beam, everything but that
+    Shot(u8),
+    /// nobody we can tell, just the impact
+    Unknown,
+    /// no beam or sound: fast-forwarded, out of sight, or the server echoing one of
+    /// ours; removed blocks still dissolve
+    Quiet,
+    /// the server reverting one of ours
+    Denied,
+    /// a placement that grew in, its effects came when it started
+    Grown,
+}
+
 thread_local!(
     static SET_BLOCK_ORIGINAL: Cell<Net_Handler> = Default::default();
 );
@@ -28,11 +65,85 @@ thread_local!(
 );
 
 thread_local!(
-    static QUEUE: RefCell<VecDeque<(Net_Handler, Vec<u8>)>> = Default::default();
+    static QUEUE: RefCell<BlockQueue<(BlockChange, Source)>> = Default::default();
+);
+
+/// A placement shown growing in, the real block comes once it's grown.
+struct Growing {
+    change: BlockChange,
+    done: Instant,
+    _animation: Rc<RefCell<BlockAnimation>>,
+}
+
+thread_local!(
+    static GROWING: RefCell<Vec<Growing>> = Default::default();
+);
+
+thread_local!(
+    static RECENT_PLACEMENTS: RefCell<RecentPlacements> = Default::default();
+);
+
+thread_local!(
+    static CLAIMS: RefCell<Claims> = Default::default();
+);
+
+thread_local!(
+    /// server changes that landed lately, how they were shown, for shots relayed
+    /// after them
+    static LANDED: RefCell<HashMap<(i32, i32, i32), (Source, Effect, Instant)>> =
+        Default::default();
+);
+
+thread_local!(
+    static BUDGET: RefCell<Budget> = Default::default();
+);
+
+thread_local!(
+    static PACING: Cell<Pacing> = Cell::new(Pacing::from_env());
+);
+
+thread_local!(
+    static MAX_LAG: Cell<MaxLag> = Cell::new(pacing::max_lag());
+);
+
+thread_local!(
+    static BUILD_ORDER: Cell<Order> = Cell::new(pacing::build_order());
+);
+
+thread_local!(
+    static ANIMATE_DISTANCE: Cell<f32> = Cell::new(pacing::animate_distance());
+);
+
+thread_local!(
+    static SKIP_OFFSCREEN: Cell<bool> = Cell::new(pacing::skip_offscreen());
+);
+
+thread_local!(
+    static FAST_FORWARD_SAMPLE: Cell<usize> = Cell::new(pacing::fast_forward_sample());
 );
 
 thread_local!(
-    static NEXT_TIME: Cell<Option<Instant>> = Default::default();
+    /// set while the server's changes are applied, `None` means the change is local
+    static APPLYING: Cell<Option<Source>> = Default::default();
+);
+
+/// A change that wasn't the server's, waiting to find out whether we clicked it.
+struct LocalChange {
+    pos: (i32, i32, i32),
+    old_block: u16,
+    new_block: u16,
+    effect: Effect,
+}
+
+thread_local!(
+    /// positions our own clicks changed this tick, from `UserEvents.BlockChanged`
+    static CLICKED: RefCell<Vec<(i32, i32, i32)>> = Default::default();
+);
+
+thread_local!(
+    /// local changes seen before their click, if there is one; the rest are physics
+    /// or commands, and left alone
+    static UNCLICKED: RefCell<Vec<LocalChange>> = Default::default();
 );
 
 thread_local!(
@@ -51,14 +162,6 @@ thread_local!(
 
 type LightingHandler = unsafe extern "C" fn(c_int, c_int, c_int, BlockID, BlockID);
 
-fn net_handlers_eq(a: Net_Handler, b: Net_Handler) -> bool {
-    match (a, b) {
-        (Some(a), Some(b)) => core::ptr::fn_addr_eq(a, b),
-        (None, None) => true,
-        _ => false,
-    }
-}
-
 fn lighting_handlers_eq(a: Option<LightingHandler>, b: Option<LightingHandler>) -> bool {
     match (a, b) {
         (Some(a), Some(b)) => core::ptr::fn_addr_eq(a, b),
@@ -67,36 +170,6 @@ fn lighting_handlers_eq(a: Option<LightingHandler>, b: Option<LightingHandler>)
     }
 }
 
-// Idempotent + wipe-aware install: if our hook is already on top, no-op.
-// If a foreign hook is stacked above our saved original, leave both alone.
-// Otherwise (first install or re-install after a slot wipe), write our hook and save current.
-macro_rules! install_hook {
-    ($slot:expr, $old:expr, $ours:expr, $eq:expr) => {{
-        let current = unsafe { $slot };
-        if !$eq(current, Some($ours)) {
-            let old = $old.get();
-            if old.is_none() || $eq(current, old) {
-                unsafe {
-                    $slot = Some($ours);
-                }
-                $old.set(current);
-            }
-        }
-    }};
-}
-
-// On-top-only uninstall: only restore if our hook is still on top.
-macro_rules! uninstall_hook {
-    ($slot:expr, $old:expr, $ours:expr, $eq:expr) => {{
-        let current = unsafe { $slot };
-        if $eq(current, Some($ours)) {
-            unsafe {
-                $slot = $old.take();
-            }
-        }
-    }};
-}
-
 fn install_all() {
     install_hook!(
         Protocol.Handlers[OPCODE__OPCODE_SET_BLOCK as usize],
@@ -140,43 +213,53 @@ fn uninstall_all() {
 }
 
 pub struct OtherBlocksModule {
+    _block_changed_handler: BlockChangedEventHandler,
     _tick_handler: TickEventHandler,
 }
 
 impl OtherBlocksModule {
     pub fn init() -> Self {
+        // only fires for our own clicks, the lighting hook sees every change
+        let mut block_changed_handler = BlockChangedEventHandler::new();
+        block_changed_handler.on(move |event| {
+            if !is_plugin_active() {
+                return;
+            }
+            clicked((event.coords.x, event.coords.y, event.coords.z));
+        });
+
         let mut tick_handler = TickEventHandler::new();
         tick_handler.on(move |_event| {
-            if let Some(next_time) = NEXT_TIME.get() {
-                let now = Instant::now();
-                if now < next_time {
-                    return;
-                }
-            }
+            // whatever didn't get a click by now never will
+            CLICKED.with_borrow_mut(|clicked| clicked.clear());
+            UNCLICKED.with_borrow_mut(|unclicked| unclicked.clear());
+
+            let now = Instant::now();
+            CLAIMS.with_borrow_mut(|claims| claims.expire(now));
+            LANDED.with_borrow_mut(|landed| {
+                landed
+                    .retain(|_, (_, _, time)| now.saturating_duration_since(*time) < CLAIM_TIMEOUT);
+            });
+            RECENT_PLACEMENTS.with_borrow_mut(|placements| placements.expire(now));
+            finish_growing(|growing| growing.done <= now);
+            fast_forward();
 
-            // make every grouping take X seconds
-            QUEUE.with_borrow_mut(|queue| {
-                if let Some((callback, mut data)) = queue.pop_front() {
-                    let now = Instant::now();
-                    NEXT_TIME.set(Some(
-                        now + Duration::from_millis((50.0 - (queue.len() as f32)).max(10.0) as u64),
-                    ));
-
-                    debug!(?callback, ?data, "real");
-                    if let Some(callback) = callback {
-                        unsafe {
-                            callback(data.as_mut_ptr());
-                        }
-                    }
-                } else {
-                    NEXT_TIME.set(None);
-                }
+            let changes = QUEUE.with_borrow_mut(|queue| {
+                let count = BUDGET.with_borrow_mut(|budget| {
+                    budget.take(&PACING.get(), queue.len(), Instant::now())
+                });
+                (0..count).map_while(|_| queue.pop()).collect::<Vec<_>>()
             });
+            for (change, source) in changes {
+                debug!(?change, ?source, "real");
+                start(&change, source);
+            }
         });
 
         install_all();
 
         Self {
+            _block_changed_handler: block_changed_handler,
             _tick_handler: tick_handler,
         }
     }
@@ -189,6 +272,11 @@ impl Module for OtherBlocksModule {
         install_all();
     }
 
+    fn on_new_map(&mut self) {
+        // all for the old map, none of it may land on the new one
+        clear_all();
+    }
+
     fn on_new_map_loaded(&mut self) {
         // ClassicLighting_SetActive() reassigned Lighting.OnBlockChanged on map load or
         // lighting-mode change; re-install. Already-on-top Protocol slots are no-ops.
@@ -196,12 +284,292 @@ impl Module for OtherBlocksModule {
     }
 
     fn free(&mut self) {
-        QUEUE.with_borrow_mut(|queue| queue.clear());
-        NEXT_TIME.set(None);
+        clear_all();
         uninstall_all();
     }
 }
 
+fn clear_all() {
+    QUEUE.with_borrow_mut(|queue| queue.clear());
+    GROWING.with_borrow_mut(|growing| growing.clear());
+    CLAIMS.with_borrow_mut(|claims| claims.clear());
+    LANDED.with_borrow_mut(|landed| landed.clear());
+    RECENT_PLACEMENTS.with_borrow_mut(|placements| placements.clear());
+    BUDGET.with_borrow_mut(|budget| budget.reset());
+    CLICKED.with_borrow_mut(|clicked| clicked.clear());
+    UNCLICKED.with_borrow_mut(|unclicked| unclicked.clear());
+}
+
+/// A player shot at `packet.target`, one beam whether the change it made comes
+/// before this, after it, or not at all.
+///
+/// Changes there are theirs for a moment.
+pub fn shot(packet: Packet) {
+    let Target::Block(BlockPos { x, y, z }) = packet.target else {
+        handle_effect(packet, Effect::Place);
+        return;
+    };
+    let pos = (x, y, z);
+    let now = Instant::now();
+    CLAIMS.with_borrow_mut(|claims| claims.claim(packet.player_id, pos, now));
+
+    // still queued, the beam comes with it
+    let queued = QUEUE.with_borrow_mut(|queue| {
+        let (_, source) = queue.get_mut(pos)?;
+        if matches!(
+            source,
+            Source::Player(_) | Source::Shot(_) | Source::Unknown
+        ) {
+            *source = Source::Player(packet.player_id);
+        }
+        Some(())
+    });
+    if queued.is_some() {
+        return;
+    }
+
+    let landed = LANDED.with_borrow(|landed| landed.get(&pos).copied());
+    match landed {
+        Some((Source::Player(player_id), ..)) if player_id == packet.player_id => {}
+        // the impact was already there, only the beam is missing
+        Some((Source::Player(_) | Source::Unknown, effect, _)) => {
+            create_laser(packet.player_id, packet.target, effect);
+        }
+        // meant to stay quiet
+        Some(_) => {}
+        // nothing changed yet, and may never; the claim keeps the change quiet if it comes
+        None => handle_effect(packet, Effect::Place),
+    }
+}
+
+/// with CPE ExtendedBlocks, which makes block ids 2 bytes
+fn extended_blocks() -> bool {
+    unsafe { Protocol.Sizes[OPCODE__OPCODE_SET_BLOCK as usize] as usize > SET_BLOCK_SIZE }
+}
+
+/// what a `Protocol.Handlers` callback gets, everything after the opcode
+unsafe fn payload<'a>(data: *mut cc_uint8, opcode: usize) -> &'a [u8] {
+    unsafe { slice::from_raw_parts(data, Protocol.Sizes[opcode] as usize - 1) }
+}
+
+/// where `entity_id` is, if they're on the map
+fn entity_position(entity_id: u8) -> Option<(f32, f32, f32)> {
+    let entity = unsafe { Entities.List[entity_id as usize].as_ref()? };
+    let position = entity.Position;
+    Some((position.x, position.y, position.z))
+}
+
+/// everyone else on the map, and where they're looking
+fn player_views() -> Vec<(u8, View)> {
+    (0..=u8::MAX)
+        .filter(|&entity_id| entity_id != ENTITY_SELF_ID)
+        .filter_map(|entity_id| {
+            let entity = unsafe { Entities.List[entity_id as usize].as_ref()? };
+            let position = entity.Position;
+            Some((
+                entity_id,
+                View {
+                    eye: (position.x, position.y + EYE_HEIGHT, position.z),
+                    yaw: entity.Yaw.to_radians(),
+                    pitch: entity.Pitch.to_radians(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// the camera, once there is one
+fn view() -> Option<View> {
+    unsafe {
+        let camera = Camera.Active.as_ref()?;
+        let get_position = camera.GetPosition?;
+        let get_orientation = camera.GetOrientation?;
+        let position = get_position(0.0);
+        let orientation = get_orientation();
+        Some(View {
+            eye: (position.x, position.y, position.z),
+            yaw: orientation.x,
+            pitch: orientation.y,
+        })
+    }
+}
+
+/// Too far away, or out of view with `SKIP_OFFSCREEN`, to be worth the wait or a beam.
+fn worth_animating(pos: (i32, i32, i32)) -> bool {
+    let Some(view) = view() else {
+        return true;
+    };
+    let max_distance = ANIMATE_DISTANCE.get();
+    if max_distance > 0.0 && view.distance(pos) > max_distance {
+        return false;
+    }
+    !SKIP_OFFSCREEN.get() || view.in_view(pos, VIEW_HALF_ANGLE)
+}
+
+fn push(change: BlockChange) {
+    let now = Instant::now();
+    // ours already had its beam when we clicked
+    let reply = RECENT_PLACEMENTS
+        .with_borrow_mut(|placements| placements.take_reply(change.pos(), change.block, now))
+        .map(|reply| match reply {
+            Reply::Echo => Source::Quiet,
+            Reply::Revert => Source::Denied,
+        });
+
+    // unless an earlier change there is still queued, which would land after it
+    if !QUEUE.with_borrow(|queue| queue.contains(change.pos())) {
+        // answers to ours shouldn't wait behind everyone else's
+        if let Some(source) = reply {
+            debug!(?change, ?source, "reply");
+            apply(&change, source);
+            return;
+        }
+        if !worth_animating(change.pos()) {
+            debug!(?change, "not animating");
+            apply(&change, Source::Quiet);
+            return;
+        }
+    }
+
+    let key = CLAIMS.with_borrow(|claims| claims.key_for(change.pos(), now));
+    let source = reply.unwrap_or_else(|| match key {
+        QueueKey::Player(player_id) => Source::Shot(player_id),
+        // whoever was looking at it when the server sent it
+        QueueKey::Region(..) => {
+            attribute(change.pos(), player_views()).map_or(Source::Unknown, Source::Player)
+        }
+    });
+    let order = BUILD_ORDER.get();
+    let rank = match (order, key) {
+        (Order::Nearest, QueueKey::Player(player_id)) => {
+            order.rank(change.pos(), entity_position(player_id))
+        }
+        // nobody to measure from, nearest to us then
+        (Order::Nearest, QueueKey::Region(..)) => {
+            order.rank(change.pos(), entity_position(ENTITY_SELF_ID))
+        }
+        _ => order.rank(change.pos(), None),
+    };
+    debug!(?key, rank, ?source, ?change);
+    QUEUE.with_borrow_mut(|queue| {
+        if let Some(superseded) = queue.push(key, change.pos(), rank, (change, source)) {
+            debug!(?superseded, "coalesced");
+        }
+    });
+}
+
+/// Apply everything past `MAX_LAG` right away, animating every
+/// `FAST_FORWARD_SAMPLE`th.
+fn fast_forward() {
+    let changes = QUEUE.with_borrow_mut(|queue| {
+        let excess = MAX_LAG.get().excess(queue.len(), &PACING.get());
+        (0..excess).map_while(|_| queue.pop()).collect::<Vec<_>>()
+    });
+    if changes.is_empty() {
+        return;
+    }
+
+    let sample = FAST_FORWARD_SAMPLE.get();
+    debug!(count = changes.len(), sample, "fast-forwarding");
+    for (i, (change, source)) in changes.iter().enumerate() {
+        if sample != 0 && i % sample == 0 {
+            apply(change, *source);
+        } else {
+            apply(change, Source::Quiet);
+        }
+    }
+}
+
+/// what's at `pos` right now, `None` outside the map
+fn world_block(pos: (i32, i32, i32)) -> Option<u16> {
+    let (x, y, z) = pos;
+    unsafe {
+        if World.Blocks.is_null()
+            || !(0..World.Width).contains(&x)
+            || !(0..World.Height).contains(&y)
+            || !(0..World.Length).contains(&z)
+        {
+            return None;
+        }
+        let index = ((y * World.Length + z) * World.Width + x) as usize;
+        let block =
+            u16::from(*World.Blocks.add(index)) | (u16::from(*World.Blocks2.add(index)) << 8);
+        Some(block & World.IdMask as u16)
+    }
+}
+
+/// Apply a queued change, placements by someone else grow in first.
+fn start(change: &BlockChange, source: Source) {
+    // an earlier one still growing there lands first
+    finish_growing(|growing| growing.change.pos() == change.pos());
+
+    let grows = matches!(
+        source,
+        Source::Player(_) | Source::Shot(_) | Source::Unknown
+    ) && world_block(change.pos()) == Some(0)
+        && change.block != 0
+        && animates(change.block);
+    if !grows {
+        apply(change, source);
+        return;
+    }
+
+    // the beam now, the block once it's grown
+    let BlockChange { x, y, z, block } = *change;
+    let target = Target::Block(BlockPos { x, y, z });
+    LANDED.with_borrow_mut(|landed| {
+        landed.insert(change.pos(), (source, Effect::Place, Instant::now()));
+    });
+    match source {
+        Source::Player(player_id) => handle_effect(Packet { player_id, target }, Effect::Place),
+        Source::Unknown => handle_impact(&target, Effect::Place),
+        _ => {}
+    }
+    let animation = grow_block(change.pos(), block);
+    GROWING.with_borrow_mut(|growing| {
+        growing.push(Growing {
+            change: *change,
+            done: Instant::now() + GROW_DURATION,
+            _animation: animation,
+        });
+    });
+}
+
+/// Put the real blocks in for the `done` ones, their beams were already shown.
+fn finish_growing(done: impl Fn(&Growing) -> bool) {
+    let done = GROWING.with_borrow_mut(|growing| {
+        growing
+            .extract_if(.., |growing| done(growing))
+            .collect::<Vec<_>>()
+    });
+    for growing in done {
+        apply(&growing.change, Source::Grown);
+    }
+}
+
+/// Run an original handler, letting the lighting hook know whose changes these are.
+fn applying(source: Source, f: impl FnOnce()) {
+    APPLYING.set(Some(source));
+    f();
+    APPLYING.set(None);
+}
+
+/// Hand `change` to the original `SET_BLOCK` handler, bulk ones included.
+fn apply(change: &BlockChange, source: Source) {
+    // or the one growing there would land on top of this later
+    finish_growing(|growing| growing.change.pos() == change.pos());
+
+    let Some(callback) = SET_BLOCK_ORIGINAL.get() else {
+        return;
+    };
+    match encode_set_block(change, extended_blocks()) {
+        Ok(mut data) => applying(source, || unsafe { callback(data.as_mut_ptr()) }),
+        Err(e) => {
+            error!("encoding {:?}: {:#?}", change, e);
+        }
+    }
+}
+
 extern "C" fn set_block_hook(data: *mut cc_uint8) {
     if !is_plugin_active() {
         if let Some(f) = SET_BLOCK_ORIGINAL.get() {
@@ -209,17 +577,17 @@ extern "C" fn set_block_hook(data: *mut cc_uint8) {
         }
         return;
     }
-    let data = unsafe {
-        slice::from_raw_parts(
-            data,
-            Protocol.Sizes[OPCODE__OPCODE_SET_BLOCK as usize] as usize,
-        )
-    };
-    let data = data.to_vec();
-    debug!(?data, "set_block_hook");
-    QUEUE.with_borrow_mut(|queue| {
-        queue.push_back((SET_BLOCK_ORIGINAL.get(), data));
-    });
+    let payload = unsafe { payload(data, OPCODE__OPCODE_SET_BLOCK as usize) };
+    debug!(?payload, "set_block_hook");
+    match decode_set_block(payload, extended_blocks()) {
+        Ok(change) => push(change),
+        Err(e) => {
+            error!("decoding set block: {:#?}", e);
+            if let Some(f) = SET_BLOCK_ORIGINAL.get() {
+                applying(Source::Unknown, || unsafe { f(data) });
+            }
+        }
+    }
 }
 
 extern "C" fn bulk_block_update_hook(data: *mut cc_uint8) {
@@ -229,17 +597,81 @@ extern "C" fn bulk_block_update_hook(data: *mut cc_uint8) {
         }
         return;
     }
-    let data = unsafe {
-        slice::from_raw_parts(
-            data,
-            Protocol.Sizes[OPCODE__OPCODE_BULK_BLOCK_UPDATE as usize] as usize,
-        )
-    };
-    let data = data.to_vec();
-    debug!(?data, "bulk_block_update_hook");
-    QUEUE.with_borrow_mut(|queue| {
-        queue.push_back((BULK_BLOCK_UPDATE_ORIGINAL.get(), data));
+    let payload = unsafe { payload(data, OPCODE__OPCODE_BULK_BLOCK_UPDATE as usize) };
+    let size = unsafe { (World.Width, World.Height, World.Length) };
+    // every block gets its own turn, and its own beam
+    match decode_bulk_block_update(payload, extended_blocks(), size) {
+        Ok(changes) => {
+            debug!(count = changes.len(), "bulk_block_update_hook");
+            for change in changes {
+                push(change);
+            }
+        }
+        Err(e) => {
+            error!("decoding bulk block update: {:#?}", e);
+            if let Some(f) = BULK_BLOCK_UPDATE_ORIGINAL.get() {
+                applying(Source::Unknown, || unsafe { f(data) });
+            }
+        }
+    }
+}
+
+/// Pieces of whatever someone else took away, and a removed block shrinking.
+fn show_removed(pos: (i32, i32, i32), old_block: BlockID, effect: Effect) {
+    if effect == Effect::Place {
+        return;
+    }
+    let (x, y, z) = pos;
+    spawn_debris(x, y, z, old_block);
+    if effect == Effect::Break {
+        dissolve_block(pos, old_block.into());
+    }
+}
+
+/// Our own click, the beam from us; the game made its own debris.
+fn show_clicked(change: LocalChange) {
+    let LocalChange {
+        pos,
+        old_block,
+        new_block,
+        effect,
+    } = change;
+    RECENT_PLACEMENTS.with_borrow_mut(|placements| {
+        placements.record(pos, old_block, new_block, Instant::now());
     });
+    let (x, y, z) = pos;
+    handle_effect(
+        Packet {
+            player_id: ENTITY_SELF_ID,
+            target: Target::Block(BlockPos { x, y, z }),
+        },
+        effect,
+    );
+}
+
+/// We clicked `pos`, whether or not the lighting hook saw it change yet.
+fn clicked(pos: (i32, i32, i32)) {
+    let change = UNCLICKED.with_borrow_mut(|unclicked| {
+        let i = unclicked.iter().position(|change| change.pos == pos)?;
+        Some(unclicked.remove(i))
+    });
+    match change {
+        Some(change) => show_clicked(change),
+        None => CLICKED.with_borrow_mut(|clicked| clicked.push(pos)),
+    }
+}
+
+/// A change that wasn't the server's, ours if we clicked there.
+fn local_change(change: LocalChange) {
+    let clicked = CLICKED.with_borrow_mut(|clicked| {
+        let i = clicked.iter().position(|&pos| pos == change.pos)?;
+        Some(clicked.remove(i))
+    });
+    if clicked.is_some() {
+        show_clicked(change);
+    } else {
+        UNCLICKED.with_borrow_mut(|unclicked| unclicked.push(change));
+    }
 }
 
 unsafe extern "C" fn lighting_on_block_changed_hook(
@@ -257,11 +689,65 @@ unsafe extern "C" fn lighting_on_block_changed_hook(
         return;
     }
 
-    if old_block == 0 && new_block != 0 {
-        debug!(?x, ?y, ?z, ?old_block, ?new_block);
-        handle_packet(Packet {
-            player_id: ENTITY_SELF_ID,
-            block_pos: IVec3 { x, y, z },
-        })
+    let Some(effect) = Effect::from_blocks(old_block.into(), new_block.into()) else {
+        return;
+    };
+    let source = APPLYING.get();
+    debug!(?x, ?y, ?z, ?old_block, ?new_block, ?effect, ?source);
+    let target = Target::Block(BlockPos { x, y, z });
+    if let Some(source) = source
+        && source != Source::Grown
+    {
+        LANDED.with_borrow_mut(|landed| {
+            landed.insert((x, y, z), (source, effect, Instant::now()));
+        });
+    }
+    match source {
+        // not from the server: our own click, or physics or a command
+        None => {
+            // the one growing there is older than this, it mustn't land on top
+            GROWING.with_borrow_mut(|growing| {
+                growing.retain(|growing| growing.change.pos() != (x, y, z));
+            });
+            local_change(LocalChange {
+                pos: (x, y, z),
+                old_block: old_block.into(),
+                new_block: new_block.into(),
+                effect,
+            });
+        }
+
+        Some(Source::Player(player_id)) => {
+            show_removed((x, y, z), old_block, effect);
+            handle_effect(Packet { player_id, target }, effect);
+        }
+
+        Some(Source::Shot(_)) => {
+            show_removed((x, y, z), old_block, effect);
+        }
+
+        Some(Source::Unknown) => {
+            show_removed((x, y, z), old_block, effect);
+            handle_impact(&target, effect);
+        }
+
+        Some(Source::Denied) => {
+            handle_effect(
+                Packet {
+                    player_id: ENTITY_SELF_ID,
+                    target,
+                },
+                Effect::Denied,
+            );
+        }
+
+        // or a mass delete would leave nothing to see at all
+        Some(Source::Quiet) => {
+            if effect == Effect::Break {
+                dissolve_block((x, y, z), old_block.into());
+            }
+        }
+
+        Some(Source::Grown) => {}
     }
 }
@@ -0,0 +1,243 @@
+use std::{env, fmt::Display, str::FromStr, time::Instant};
+
+use anyhow::{Context, Error, Result, bail};
+use tracing::warn;
+
+use super::block_queue::Order;
+
+/// how far behind the server other players' blocks may get, `500` blocks or `10s`
+pub const MAX_LAG_ENV: &str = "TOOLGUN_MAX_LAG";
+/// animate every Nth block when fast-forwarding, 0 for none
+pub const FAST_FORWARD_SAMPLE_ENV: &str = "TOOLGUN_FAST_FORWARD_SAMPLE";
+/// other players' blocks per second with nothing else queued
+pub const BLOCKS_PER_SECOND_ENV: &str = "TOOLGUN_BLOCKS_PER_SECOND";
+/// how the rate grows with the backlog, `constant`, `sqrt` or `linear`
+pub const PACING_CURVE_ENV: &str = "TOOLGUN_PACING_CURVE";
+/// blocks further than this from the camera change without animation, 0 for no limit
+pub const ANIMATE_DISTANCE_ENV: &str = "TOOLGUN_ANIMATE_DISTANCE";
+/// `true` to also skip blocks that aren't in view
+pub const SKIP_OFFSCREEN_ENV: &str = "TOOLGUN_SKIP_OFFSCREEN";
+/// which queued block goes next, `arrival`, `bottom_up`, `nearest` or `wave`
+pub const BUILD_ORDER_ENV: &str = "TOOLGUN_BUILD_ORDER";
+
+pub const DEFAULT_MAX_LAG: MaxLag = MaxLag::Seconds(10.0);
+pub const DEFAULT_FAST_FORWARD_SAMPLE: usize = 0;
+pub const DEFAULT_BLOCKS_PER_SECOND: f32 = 20.0;
+pub const DEFAULT_PACING_CURVE: Curve = Curve::Sqrt;
+pub const DEFAULT_BUILD_ORDER: Order = Order::Arrival;
+pub const DEFAULT_ANIMATE_DISTANCE: f32 = 96.0;
+pub const DEFAULT_SKIP_OFFSCREEN: bool = false;
+
+/// Past this the queue is fast-forwarded, so a huge fill doesn't leave the world
+/// out of sync with the server for minutes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaxLag {
+    Blocks(usize),
+    Seconds(f32),
+}
+
+impl FromStr for MaxLag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(seconds) = s.strip_suffix('s') {
+            let seconds: f32 = seconds.trim().parse().context("bad seconds")?;
+            if seconds.is_nan() || seconds < 0.0 {
+                bail!("negative seconds");
+            }
+            Ok(Self::Seconds(seconds))
+        } else {
+            Ok(Self::Blocks(s.parse().context("bad block count")?))
+        }
+    }
+}
+
+impl MaxLag {
+    /// How many of `len` queued changes to apply right away, when draining at `pacing`.
+    pub fn excess(&self, len: usize, pacing: &Pacing) -> usize {
+        let max = match *self {
+            Self::Blocks(blocks) => blocks,
+            Self::Seconds(seconds) => (seconds * pacing.rate(len)) as usize,
+        };
+        len.saturating_sub(max)
+    }
+}
+
+/// How the drain rate grows with the backlog, in seconds of it at the base rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    Constant,
+    Sqrt,
+    Linear,
+}
+
+impl FromStr for Curve {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "constant" => Self::Constant,
+            "sqrt" => Self::Sqrt,
+            "linear" => Self::Linear,
+            other => bail!("unknown curve {:?}", other),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pacing {
+    pub blocks_per_second: f32,
+    pub curve: Curve,
+}
+
+impl Pacing {
+    pub fn from_env() -> Self {
+        let blocks_per_second = from_env(BLOCKS_PER_SECOND_ENV, DEFAULT_BLOCKS_PER_SECOND);
+        Self {
+            blocks_per_second: if blocks_per_second > 0.0 {
+                blocks_per_second
+            } else {
+                warn!("{} must be positive", BLOCKS_PER_SECOND_ENV);
+                DEFAULT_BLOCKS_PER_SECOND
+            },
+            curve: from_env(PACING_CURVE_ENV, DEFAULT_PACING_CURVE),
+        }
+    }
+
+    /// blocks per second with `len` queued
+    pub fn rate(&self, len: usize) -> f32 {
+        let backlog = len as f32 / self.blocks_per_second;
+        let factor = match self.curve {
+            Curve::Constant => 1.0,
+            Curve::Sqrt => 1.0 + backlog.sqrt(),
+            Curve::Linear => 1.0 + backlog,
+        };
+        self.blocks_per_second * factor
+    }
+}
+
+/// Changes owed since the last tick, so the rate doesn't depend on the tick rate.
+#[derive(Debug, Default)]
+pub struct Budget {
+    last: Option<Instant>,
+    owed: f32,
+}
+
+impl Budget {
+    /// How many of `len` queued changes to apply now, the first one right away.
+    pub fn take(&mut self, pacing: &Pacing, len: usize, now: Instant) -> usize {
+        if len == 0 {
+            self.reset();
+            return 0;
+        }
+        let Some(last) = self.last.replace(now) else {
+            return 1;
+        };
+
+        let elapsed = now.saturating_duration_since(last).as_secs_f32();
+        // don't save up for a burst while the queue was short
+        self.owed = (self.owed + elapsed * pacing.rate(len)).min(len as f32);
+        let count = self.owed as usize;
+        self.owed -= count as f32;
+        count
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.owed = 0.0;
+    }
+}
+
+fn from_env<T>(name: &str, default: T) -> T
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let Some(value) = env::var_os(name) else {
+        return default;
+    };
+    match value.to_string_lossy().parse() {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("bad {} {:?}: {:#}", name, value, e);
+            default
+        }
+    }
+}
+
+pub fn max_lag() -> MaxLag {
+    from_env(MAX_LAG_ENV, DEFAULT_MAX_LAG)
+}
+
+pub fn fast_forward_sample() -> usize {
+    from_env(FAST_FORWARD_SAMPLE_ENV, DEFAULT_FAST_FORWARD_SAMPLE)
+}
+
+pub fn build_order() -> Order {
+    from_env(BUILD_ORDER_ENV, DEFAULT_BUILD_ORDER)
+}
+
+pub fn animate_distance() -> f32 {
+    from_env(ANIMATE_DISTANCE_ENV, DEFAULT_ANIMATE_DISTANCE)
+}
+
+pub fn skip_offscreen() -> bool {
+    from_env(SKIP_OFFSCREEN_ENV, DEFAULT_SKIP_OFFSCREEN)
+}
+
+#[test]
+fn test_max_lag() {
+    assert_eq!("500".parse::<MaxLag>().unwrap(), MaxLag::Blocks(500));
+    assert_eq!("2.5s".parse::<MaxLag>().unwrap(), MaxLag::Seconds(2.5));
+    assert!("-1s".parse::<MaxLag>().is_err());
+    assert!("lots".parse::<MaxLag>().is_err());
+
+    let pacing = Pacing {
+        blocks_per_second: 20.0,
+        curve: Curve::Constant,
+    };
+    assert_eq!(MaxLag::Blocks(500).excess(20_000, &pacing), 19_500);
+    assert_eq!(MaxLag::Blocks(500).excess(100, &pacing), 0);
+    assert_eq!(MaxLag::Seconds(10.0).excess(20_000, &pacing), 19_800);
+    assert_eq!(MaxLag::Seconds(0.0).excess(3, &pacing), 3);
+}
+
+#[test]
+fn test_pacing() {
+    use std::time::Duration;
+
+    let mut pacing = Pacing {
+        blocks_per_second: 20.0,
+        curve: Curve::Constant,
+    };
+    assert_eq!(pacing.rate(80), 20.0);
+    pacing.curve = "sqrt".parse().unwrap();
+    assert_eq!(pacing.rate(80), 60.0);
+    pacing.curve = "linear".parse().unwrap();
+    assert_eq!(pacing.rate(80), 100.0);
+    assert!("fast".parse::<Curve>().is_err());
+
+    pacing.curve = Curve::Constant;
+    let now = Instant::now();
+    let mut budget = Budget::default();
+    assert_eq!(budget.take(&pacing, 100, now), 1);
+    // several per tick, and fractions carry over
+    assert_eq!(
+        budget.take(&pacing, 99, now + Duration::from_millis(175)),
+        3
+    );
+    assert_eq!(
+        budget.take(&pacing, 96, now + Duration::from_millis(200)),
+        1
+    );
+    assert_eq!(
+        budget.take(&pacing, 95, now + Duration::from_millis(210)),
+        0
+    );
+
+    // never more than is queued
+    assert_eq!(budget.take(&pacing, 2, now + Duration::from_secs(10)), 2);
+    assert_eq!(budget.take(&pacing, 0, now + Duration::from_secs(10)), 0);
+    assert_eq!(budget.take(&pacing, 5, now + Duration::from_secs(20)), 1);
+}
@@ -0,0 +1,97 @@
+/// how far off where we're looking still counts as on screen, wider than the
+/// default field of view so blocks at the edges still animate
+pub const VIEW_HALF_ANGLE: f32 = 75.0 * std::f32::consts::PI / 180.0;
+
+/// closer than this always counts as in view, the angle to a block you're
+/// standing next to says little
+const NEAR_DISTANCE: f32 = 2.0;
+
+/// Where the camera is and which way it looks, yaw and pitch in radians like
+/// `Camera.Active->GetOrientation` returns them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    pub eye: (f32, f32, f32),
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl View {
+    /// same as the game's `Vec3_GetDirVector`
+    pub fn direction(&self) -> (f32, f32, f32) {
+        let (yaw_sin, yaw_cos) = self.yaw.sin_cos();
+        let (pitch_sin, pitch_cos) = self.pitch.sin_cos();
+        (pitch_cos * yaw_sin, -pitch_sin, -pitch_cos * yaw_cos)
+    }
+
+    /// from the eye to the center of the block at `pos`
+    fn offset(&self, pos: (i32, i32, i32)) -> (f32, f32, f32) {
+        (
+            pos.0 as f32 + 0.5 - self.eye.0,
+            pos.1 as f32 + 0.5 - self.eye.1,
+            pos.2 as f32 + 0.5 - self.eye.2,
+        )
+    }
+
+    pub fn distance(&self, pos: (i32, i32, i32)) -> f32 {
+        let (x, y, z) = self.offset(pos);
+        (x * x + y * y + z * z).sqrt()
+    }
+
+    /// How far along where we're looking the block at `pos` is, and how far off
+    /// to the side; `None` if it's behind us.
+    pub fn ray_distance(&self, pos: (i32, i32, i32)) -> Option<(f32, f32)> {
+        let (x, y, z) = self.offset(pos);
+        let (dx, dy, dz) = self.direction();
+        let along = x * dx + y * dy + z * dz;
+        if along < 0.0 {
+            return None;
+        }
+        let off = (x * x + y * y + z * z - along * along).max(0.0).sqrt();
+        Some((along, off))
+    }
+
+    /// Whether `pos` is within `half_angle` radians of where we're looking.
+    pub fn in_view(&self, pos: (i32, i32, i32), half_angle: f32) -> bool {
+        let distance = self.distance(pos);
+        if distance < NEAR_DISTANCE {
+            return true;
+        }
+        let (x, y, z) = self.offset(pos);
+        let (dx, dy, dz) = self.direction();
+        (x * dx + y * dy + z * dz) / distance >= half_angle.cos()
+    }
+}
+
+#[test]
+fn test_view() {
+    use std::f32::consts::FRAC_PI_2;
+
+    // looking north, -z
+    let view = View {
+        eye: (0.5, 0.5, 0.5),
+        yaw: 0.0,
+        pitch: 0.0,
+    };
+    let (x, y, z) = view.direction();
+    assert!(x.abs() < 1e-6 && y.abs() < 1e-6 && (z + 1.0).abs() < 1e-6);
+
+    assert_eq!(view.distance((3, 4, 0)), 5.0);
+    assert_eq!(view.ray_distance((3, 0, -4)), Some((4.0, 3.0)));
+    assert_eq!(view.ray_distance((0, 0, 4)), None);
+
+    let half_angle = 45f32.to_radians();
+    assert!(view.in_view((0, 0, -10), half_angle));
+    assert!(view.in_view((5, 0, -10), half_angle));
+    assert!(!view.in_view((20, 0, -10), half_angle));
+    assert!(!view.in_view((0, 0, 10), half_angle));
+    // right behind us, but too close to tell
+    assert!(view.in_view((0, 0, 1), half_angle));
+
+    // turned to face east, +x
+    let view = View {
+        yaw: FRAC_PI_2,
+        ..view
+    };
+    assert!(view.in_view((20, 0, -10), half_angle));
+    assert!(!view.in_view((0, 0, -10), half_angle));
+}
@@ -0,0 +1,41 @@
+use classicube_sys::Net_Handler;
+
+pub fn net_handlers_eq(a: Net_Handler, b: Net_Handler) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => core::ptr::fn_addr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// Idempotent + wipe-aware install: if our hook is already on top, no-op.
+// If a foreign hook is stacked above our saved original, leave both alone.
+// Otherwise (first install or re-install after a slot wipe), write our hook and save current.
+macro_rules! install_hook {
+    ($slot:expr, $old:expr, $ours:expr, $eq:expr) => {{
+        let current = unsafe { $slot };
+        if !$eq(current, Some($ours)) {
+            let old = $old.get();
+            if old.is_none() || $eq(current, old) {
+                unsafe {
+                    $slot = Some($ours);
+                }
+                $old.set(current);
+            }
+        }
+    }};
+}
+
+// On-top-only uninstall: only restore if our hook is still on top.
+macro_rules! uninstall_hook {
+    ($slot:expr, $old:expr, $ours:expr, $eq:expr) => {{
+        let current = unsafe { $slot };
+        if $eq(current, Some($ours)) {
+            unsafe {
+                $slot = $old.take();
+            }
+        }
+    }};
+}
+
+pub(crate) use {install_hook, uninstall_hook};
@@ -1,18 +1,28 @@
 pub mod async_manager;
+pub mod effect;
 pub mod events;
+pub mod hook;
 pub mod logger;
 pub mod module;
 pub mod networking;
 pub mod render;
 pub mod sound;
 
-use std::cell::{Cell, RefCell};
+use std::{
+    cell::{Cell, RefCell},
+    env,
+};
 
 use classicube_sys::Server;
 
 use crate::plugin::{
-    async_manager::AsyncManagerModule, events::EventsModule, logger::LoggerModule, module::Module,
-    networking::NetworkingModule, render::RenderModule, sound::SoundModule,
+    async_manager::AsyncManagerModule,
+    events::EventsModule,
+    logger::LoggerModule,
+    module::Module,
+    networking::{NetworkingModule, recording::REPLAY_ENV},
+    render::RenderModule,
+    sound::SoundModule,
 };
 
 thread_local!(
@@ -43,7 +53,10 @@ impl MainModule {
         let render = RenderModule::init();
         let sound = SoundModule::init();
         let events = EventsModule::init();
-        let networking = (unsafe { Server.IsSinglePlayer } == 0).then(NetworkingModule::init);
+        // replays also work offline, e.g. on a local single player map
+        let networking = (unsafe { Server.IsSinglePlayer } == 0
+            || env::var_os(REPLAY_ENV).is_some())
+        .then(NetworkingModule::init);
 
         Self {
             logger,
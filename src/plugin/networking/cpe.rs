@@ -0,0 +1,65 @@
+use std::{cell::Cell, slice};
+
+use classicube_sys::{Net_Handler, OPCODE__OPCODE_EXT_ENTRY, Protocol, cc_uint8};
+use tracing::debug;
+
+use crate::plugin::{
+    hook::{install_hook, net_handlers_eq, uninstall_hook},
+    is_plugin_active,
+};
+
+const PLUGIN_MESSAGES_EXT_NAME: &str = "PluginMessages";
+/// ExtEntry is a 64 byte space-padded name followed by an i32 version
+const EXT_NAME_LENGTH: usize = 64;
+
+thread_local!(
+    static EXT_ENTRY_ORIGINAL: Cell<Net_Handler> = Default::default();
+);
+
+thread_local!(
+    static SUPPORTS_PLUGIN_MESSAGES: Cell<bool> = const { Cell::new(false) };
+);
+
+/// Whether the server listed the PluginMessages extension during CPE negotiation;
+/// without it vanilla servers would get packets they don't understand.
+pub fn supports_plugin_messages() -> bool {
+    SUPPORTS_PLUGIN_MESSAGES.get()
+}
+
+pub fn install() {
+    install_hook!(
+        Protocol.Handlers[OPCODE__OPCODE_EXT_ENTRY as usize],
+        EXT_ENTRY_ORIGINAL,
+        ext_entry_hook,
+        net_handlers_eq
+    );
+}
+
+pub fn uninstall() {
+    uninstall_hook!(
+        Protocol.Handlers[OPCODE__OPCODE_EXT_ENTRY as usize],
+        EXT_ENTRY_ORIGINAL,
+        ext_entry_hook,
+        net_handlers_eq
+    );
+}
+
+/// new connection, it has to tell us again
+pub fn reset() {
+    SUPPORTS_PLUGIN_MESSAGES.set(false);
+}
+
+extern "C" fn ext_entry_hook(data: *mut cc_uint8) {
+    if is_plugin_active() {
+        let name = unsafe { slice::from_raw_parts(data, EXT_NAME_LENGTH) };
+        let name = String::from_utf8_lossy(name);
+        if name.trim_end() == PLUGIN_MESSAGES_EXT_NAME {
+            debug!("server supports {}", PLUGIN_MESSAGES_EXT_NAME);
+            SUPPORTS_PLUGIN_MESSAGES.set(true);
+        }
+    }
+
+    if let Some(f) = EXT_ENTRY_ORIGINAL.get() {
+        unsafe { f(data) }
+    }
+}
@@ -1,57 +1,364 @@
-pub mod packet;
+pub mod cpe;
+pub mod rate_limit;
+pub mod recording;
 
-use std::io::Cursor;
+use std::{
+    cell::{Cell, RefCell},
+    env,
+    time::Instant,
+};
 
-use anyhow::Error;
-use classicube_helpers::{async_manager, events::net::PluginMessageReceivedEventHandler};
-use classicube_sys::CPE_SendPluginMessage;
-use tracing::{debug, error};
+use anyhow::{Error, Result};
+use classicube_helpers::{
+    async_manager,
+    entities::ENTITY_SELF_ID,
+    events::{net::PluginMessageReceivedEventHandler, user::BlockChangedEventHandler},
+    tick::TickEventHandler,
+};
+use classicube_sys::{CPE_SendPluginMessage, Entities, Server};
+use toolgun_protocol::{
+    BlockPos, CoordEncoding, DEFAULT_CHANNEL, Handshake, LargeMessage, Message,
+    PLUGIN_MESSAGE_LENGTH, PROTOCOL_VERSION, Packet, Reassembler, Target,
+};
+use tracing::{debug, error, info, warn};
 
-use self::packet::{Packet, handle_packet};
-use crate::plugin::module::Module;
+use self::{
+    rate_limit::{ErrorLog, RateLimiter},
+    recording::{Recorder, Replay},
+};
+use crate::plugin::{
+    effect::Effect,
+    events::other_blocks,
+    module::Module,
+    render::{
+        create_laser, get_target_position,
+        laser::texture::{decode_beam_texture, set_server_texture},
+    },
+    sound::play_sound,
+};
 
-pub const CHANNEL: u8 = 71;
-pub const PLUGIN_MESSAGE_LENGTH: usize = 64;
+/// overrides `DEFAULT_CHANNEL`, for servers that already use it for something else
+pub const CHANNEL_ENV: &str = "TOOLGUN_CHANNEL";
+
+thread_local!(
+    static CHANNEL: Cell<u8> = Cell::new(configured_channel());
+);
+
+thread_local!(
+    static HANDSHAKE: Cell<Option<Handshake>> = Default::default();
+);
+
+thread_local!(
+    static RATE_LIMITER: RefCell<RateLimiter> = RefCell::new(RateLimiter::new(Instant::now()));
+);
+
+thread_local!(
+    static ERROR_LOG: RefCell<ErrorLog> = RefCell::new(ErrorLog::new(Instant::now()));
+);
+
+thread_local!(
+    static REASSEMBLER: RefCell<Reassembler> = Default::default();
+);
+
+thread_local!(
+    static RECORDER: RefCell<Option<Recorder>> = Default::default();
+);
+
+thread_local!(
+    static REPLAY: RefCell<Option<Replay>> = Default::default();
+);
+
+fn handle_plugin_message(data: &[u8]) {
+    match Message::decode(data, HANDSHAKE.get()) {
+        Ok(message) => handle_message(message),
+        Err(e) => {
+            if ERROR_LOG.with_borrow_mut(|error_log| error_log.allow(Instant::now())) {
+                error!("decoding message: {:#?}", e);
+            }
+        }
+    }
+}
+
+fn handle_message(message: Message) {
+    match message {
+        Message::Handshake(handshake) => {
+            debug!("handshake {:?}", handshake);
+            if !handshake.is_supported() {
+                warn!(
+                    "ignoring handshake with unsupported protocol version {}",
+                    handshake.version
+                );
+                return;
+            }
+            // 0 is no preference, not a channel to move to
+            if let Some(channel) = handshake.requested_channel()
+                && channel != CHANNEL.get()
+            {
+                info!("server moved us to channel {}", channel);
+                CHANNEL.set(channel);
+            }
+            HANDSHAKE.set(Some(handshake));
+        }
+
+        Message::Packet(packet) => {
+            debug!("packet {:?}", packet);
+            if !RATE_LIMITER
+                .with_borrow_mut(|limiter| limiter.allow(packet.player_id, Instant::now()))
+            {
+                return;
+            }
+            handle_packet(packet);
+        }
+
+        Message::Fragment(fragment) => {
+            let now = Instant::now();
+            // the rest of a message is already paid for
+            if fragment.sequence == 0
+                && !RATE_LIMITER
+                    .with_borrow_mut(|limiter| limiter.allow_message(fragment.count(), now))
+            {
+                return;
+            }
+            match REASSEMBLER.with_borrow_mut(|reassembler| reassembler.receive(fragment, now)) {
+                Ok(Some(data)) => handle_large_message(data),
+                Ok(None) => {}
+                Err(e) => {
+                    if ERROR_LOG.with_borrow_mut(|error_log| error_log.allow(now)) {
+                        error!("reassembling message: {:#?}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A message from `REPLAY`, its players were on another server.
+fn handle_replayed_message(message: Message) {
+    match message {
+        Message::Packet(mut packet) => {
+            let here = unsafe { !Entities.List[packet.player_id as usize].is_null() };
+            if !here {
+                // someone has to fire it
+                packet.player_id = ENTITY_SELF_ID;
+            }
+            handle_message(Message::Packet(packet));
+        }
+
+        message => handle_message(message),
+    }
+}
+
+/// A relayed shot, its beam looks like the change it made.
+pub fn handle_packet(packet: Packet) {
+    other_blocks::shot(packet);
+}
+
+/// A beam from `packet.player_id` to its target, looking like `effect`.
+pub fn handle_effect(packet: Packet, effect: Effect) {
+    handle_impact(&packet.target, effect);
+    create_laser(packet.player_id, packet.target, effect);
+}
+
+/// Just the sound, for changes we can't tell who made.
+pub fn handle_impact(target: &Target, effect: Effect) {
+    if let Some(pos) = get_target_position(target) {
+        play_sound(pos, effect);
+    }
+}
+
+/// A message that was split into `Fragment`s, reassembled.
+fn handle_large_message(data: Vec<u8>) {
+    debug!("large message of {} bytes", data.len());
+
+    match LargeMessage::decode(&data) {
+        Ok(LargeMessage::BeamTexture(image)) => match decode_beam_texture(&image) {
+            Ok(texture) => {
+                debug!("using server texture {}x{}", texture.width, texture.height);
+                set_server_texture(Some(texture));
+            }
+
+            Err(e) => {
+                error!("decoding beam texture: {:#?}", e);
+            }
+        },
+
+        Err(e) => {
+            error!("decoding large message: {:#?}", e);
+        }
+    }
+}
+
+/// the channel we announce ourselves on, before the server gets a say
+fn configured_channel() -> u8 {
+    let Some(channel) = env::var_os(CHANNEL_ENV) else {
+        return DEFAULT_CHANNEL;
+    };
+    match channel.to_string_lossy().parse() {
+        Ok(channel) => channel,
+        Err(e) => {
+            warn!("bad {} {:?}: {}", CHANNEL_ENV, channel, e);
+            DEFAULT_CHANNEL
+        }
+    }
+}
+
+fn send_plugin_message(mut data: [u8; PLUGIN_MESSAGE_LENGTH]) {
+    if !cpe::supports_plugin_messages() {
+        return;
+    }
+
+    unsafe {
+        CPE_SendPluginMessage(CHANNEL.get(), data.as_mut_ptr());
+    }
+}
+
+fn send_message(message: &Message) -> Result<()> {
+    let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+    message.encode(&mut data.as_mut_slice(), HANDSHAKE.get())?;
+    send_plugin_message(data);
+    Ok(())
+}
+
+/// Tell the server we fired, so it can relay the beam to everyone else.
+///
+/// Only sent once the server has answered our handshake; legacy servers treat any
+/// message on our channel as "this client has the plugin".
+pub fn send_packet(packet: Packet) -> Result<()> {
+    if HANDSHAKE.get().is_none() {
+        return Ok(());
+    }
+
+    send_message(&Message::Packet(packet))
+}
 
 pub struct NetworkingModule {
     _plugin_message_handler: PluginMessageReceivedEventHandler,
+    _block_changed_handler: BlockChangedEventHandler,
+    _tick_handler: TickEventHandler,
 }
 
 impl NetworkingModule {
     pub fn init() -> Self {
         let mut plugin_message_handler = PluginMessageReceivedEventHandler::new();
         plugin_message_handler.on(move |event| {
-            if event.channel != CHANNEL {
+            if event.channel != CHANNEL.get() {
                 return;
             }
 
-            match Packet::decode(&mut Cursor::new(&event.data)) {
-                Ok(packet) => {
-                    debug!("packet {:?}", packet);
-                    handle_packet(packet);
+            RECORDER.with_borrow_mut(|option| {
+                if let Some(recorder) = option.as_mut()
+                    && let Err(e) = recorder.record(&event.data)
+                {
+                    error!("recording: {:#?}", e);
+                    // don't spam the same error for every message
+                    *option = None;
                 }
+            });
 
-                Err(e) => {
-                    error!("decoding packet: {:#?}", e);
+            handle_plugin_message(&event.data);
+        });
+
+        // only fires for our own clicks, not for blocks set by the server
+        let mut block_changed_handler = BlockChangedEventHandler::new();
+        block_changed_handler.on(move |event| {
+            if event.block == 0 {
+                return;
+            }
+
+            // the server fills in our real id when relaying
+            if let Err(e) = send_packet(Packet {
+                player_id: ENTITY_SELF_ID,
+                target: Target::Block(BlockPos {
+                    x: event.coords.x,
+                    y: event.coords.y,
+                    z: event.coords.z,
+                }),
+            }) {
+                error!("sending packet: {:#?}", e);
+            }
+        });
+
+        let mut tick_handler = TickEventHandler::new();
+        tick_handler.on(move |_event| {
+            let now = Instant::now();
+            REASSEMBLER.with_borrow_mut(|reassembler| reassembler.expire(now));
+
+            while let Some(result) = REPLAY.with_borrow_mut(|option| {
+                let replay = option.as_mut()?;
+                let result = replay.next_message(now);
+                if replay.is_finished() {
+                    debug!("replay finished");
+                    *option = None;
+                }
+                result
+            }) {
+                match result {
+                    Ok(message) => handle_replayed_message(message),
+                    Err(e) => {
+                        if ERROR_LOG.with_borrow_mut(|error_log| error_log.allow(now)) {
+                            error!("decoding replayed message: {:#?}", e);
+                        }
+                    }
                 }
             }
         });
 
+        RECORDER.set(Recorder::from_env());
+        cpe::install();
+
         Self {
             _plugin_message_handler: plugin_message_handler,
+            _block_changed_handler: block_changed_handler,
+            _tick_handler: tick_handler,
         }
     }
 }
 
 impl Module for NetworkingModule {
+    fn reset(&mut self) {
+        // new connection, the server has to negotiate again
+        HANDSHAKE.set(None);
+        CHANNEL.set(configured_channel());
+        RATE_LIMITER.set(RateLimiter::new(Instant::now()));
+        ERROR_LOG.set(ErrorLog::new(Instant::now()));
+        REASSEMBLER.set(Default::default());
+
+        // Protocol.OnReset wiped EXT_ENTRY back to default before this callback;
+        // the new server will list its extensions again
+        cpe::reset();
+        cpe::install();
+    }
+
+    fn free(&mut self) {
+        cpe::uninstall();
+        RECORDER.set(None);
+        REPLAY.set(None);
+    }
+
     fn on_new_map_loaded(&mut self) {
+        // (re)start from the beginning on every map
+        REPLAY.set(Replay::from_env());
+
+        if unsafe { Server.IsSinglePlayer } != 0 {
+            return;
+        }
+        if !cpe::supports_plugin_messages() {
+            info!("server doesn't support PluginMessages, only showing local effects");
+            return;
+        }
+
         async_manager::spawn_local_on_main_thread(async move {
             if let Err(e) = async move {
-                // send empty packet to tell server we have this plugin
-                let mut data = Vec::with_capacity(PLUGIN_MESSAGE_LENGTH);
-                unsafe {
-                    CPE_SendPluginMessage(CHANNEL, data.as_mut_ptr());
+                // tell server we have this plugin, and the newest coord encoding we understand;
+                // old servers only care that a message arrived
+                let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+                Handshake {
+                    version: PROTOCOL_VERSION,
+                    coord_encoding: CoordEncoding::I32,
+                    channel: CHANNEL.get(),
                 }
+                .encode(&mut data.as_mut_slice())?;
+                send_plugin_message(data);
                 Ok::<_, Error>(())
             }
             .await
@@ -0,0 +1,220 @@
+use std::{collections::HashMap, time::Instant};
+
+use toolgun_protocol::fragment::MAX_FRAGMENTS;
+use tracing::{info, warn};
+
+/// every player can fire this many times per second...
+const PLAYER_PER_SECOND: f32 = 10.0;
+/// ...after a burst of this many
+const PLAYER_BURST: f32 = 20.0;
+
+/// all players together, so a server can't get around it by rotating ids
+const GLOBAL_PER_SECOND: f32 = 50.0;
+const GLOBAL_BURST: f32 = 100.0;
+
+/// messages split into fragments have their own budget, counted in fragments;
+/// the biggest message fits at once...
+const FRAGMENT_BURST: f32 = MAX_FRAGMENTS as f32;
+/// ...and another one every 4 seconds after that
+const FRAGMENT_PER_SECOND: f32 = 64.0;
+
+/// errors from bad messages logged per second, after a burst of this many
+const ERROR_PER_SECOND: f32 = 1.0;
+const ERROR_BURST: f32 = 5.0;
+
+pub struct TokenBucket {
+    capacity: f32,
+    per_second: f32,
+    tokens: f32,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f32, per_second: f32, now: Instant) -> Self {
+        Self {
+            capacity,
+            per_second,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed.as_secs_f32() * self.per_second).min(self.capacity);
+    }
+
+    fn has_tokens(&self, count: f32) -> bool {
+        self.tokens >= count
+    }
+
+    fn take(&mut self, count: f32) {
+        self.tokens -= count;
+    }
+}
+
+/// Budget for incoming packets, since every one of them costs a texture and a sound,
+/// and for fragments, which cost reassembly.
+pub struct RateLimiter {
+    global: TokenBucket,
+    players: HashMap<u8, TokenBucket>,
+    fragments: TokenBucket,
+    /// how many were dropped in the current flood
+    dropped: usize,
+    flood_start: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            global: TokenBucket::new(GLOBAL_BURST, GLOBAL_PER_SECOND, now),
+            players: HashMap::new(),
+            fragments: TokenBucket::new(FRAGMENT_BURST, FRAGMENT_PER_SECOND, now),
+            dropped: 0,
+            flood_start: None,
+        }
+    }
+
+    /// `false` if this packet is over budget and should be dropped.
+    pub fn allow(&mut self, player_id: u8, now: Instant) -> bool {
+        let player = self
+            .players
+            .entry(player_id)
+            .or_insert_with(|| TokenBucket::new(PLAYER_BURST, PLAYER_PER_SECOND, now));
+        player.refill(now);
+        self.global.refill(now);
+
+        let allowed = player.has_tokens(1.0) && self.global.has_tokens(1.0);
+        if allowed {
+            player.take(1.0);
+            self.global.take(1.0);
+        }
+        self.count(allowed, now)
+    }
+
+    /// `false` if a new message of `fragments` is over budget and should be dropped.
+    ///
+    /// Paid for up front with its first fragment, dropping any later one would
+    /// only break a message that already started.
+    pub fn allow_message(&mut self, fragments: usize, now: Instant) -> bool {
+        self.fragments.refill(now);
+
+        let fragments = fragments as f32;
+        let allowed = self.fragments.has_tokens(fragments);
+        if allowed {
+            self.fragments.take(fragments);
+        }
+        self.count(allowed, now)
+    }
+
+    /// keeps track of floods, passing `allowed` through
+    fn count(&mut self, allowed: bool, now: Instant) -> bool {
+        if allowed {
+            if let Some(flood_start) = self.flood_start.take() {
+                info!(
+                    "flood over, dropped {} packets in {:?}",
+                    self.dropped,
+                    now.saturating_duration_since(flood_start)
+                );
+                self.dropped = 0;
+            }
+            true
+        } else {
+            if self.flood_start.is_none() {
+                // only once per flood, logging every drop would be a flood of its own
+                warn!("too many toolgun packets, dropping until they slow down");
+                self.flood_start = Some(now);
+            }
+            self.dropped += 1;
+            false
+        }
+    }
+}
+
+/// Whether to log another error about a bad message, so a misbehaving server
+/// can't flood the log either.
+pub struct ErrorLog {
+    bucket: TokenBucket,
+    /// how many weren't logged since the last one that was
+    suppressed: usize,
+}
+
+impl ErrorLog {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            bucket: TokenBucket::new(ERROR_BURST, ERROR_PER_SECOND, now),
+            suppressed: 0,
+        }
+    }
+
+    /// `false` if this error should go unlogged.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        self.bucket.refill(now);
+        if !self.bucket.has_tokens(1.0) {
+            self.suppressed += 1;
+            return false;
+        }
+
+        self.bucket.take(1.0);
+        if self.suppressed > 0 {
+            warn!(
+                "{} more errors about bad messages weren't logged",
+                self.suppressed
+            );
+            self.suppressed = 0;
+        }
+        true
+    }
+}
+
+#[test]
+fn test_rate_limiter() {
+    use std::time::Duration;
+
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(start);
+
+    let allowed = (0..100).filter(|_| limiter.allow(1, start)).count();
+    assert_eq!(allowed, PLAYER_BURST as usize);
+
+    // another player has their own budget
+    assert!(limiter.allow(2, start));
+
+    // refills over time
+    let later = start + Duration::from_secs(1);
+    let allowed = (0..100).filter(|_| limiter.allow(1, later)).count();
+    assert_eq!(allowed, PLAYER_PER_SECOND as usize);
+
+    // global budget caps everyone together
+    let mut limiter = RateLimiter::new(start);
+    let allowed = (0..=u8::MAX)
+        .flat_map(|player_id| [player_id; 2])
+        .filter(|&player_id| limiter.allow(player_id, start))
+        .count();
+    assert_eq!(allowed, GLOBAL_BURST as usize);
+
+    // messages don't take from packets, or the other way around
+    let mut limiter = RateLimiter::new(start);
+    assert!(limiter.allow_message(MAX_FRAGMENTS, start));
+    assert!(!limiter.allow_message(1, start));
+    assert!(limiter.allow(1, start));
+    let later = start + Duration::from_secs(1);
+    assert!(!limiter.allow_message(MAX_FRAGMENTS, later));
+    let allowed = (0..1000)
+        .filter(|_| limiter.allow_message(1, later))
+        .count();
+    assert_eq!(allowed, FRAGMENT_PER_SECOND as usize);
+}
+
+#[test]
+fn test_error_log() {
+    use std::time::Duration;
+
+    let start = Instant::now();
+    let mut error_log = ErrorLog::new(start);
+    let logged = (0..100).filter(|_| error_log.allow(start)).count();
+    assert_eq!(logged, ERROR_BURST as usize);
+    assert!(error_log.allow(start + Duration::from_secs(1)));
+    assert!(!error_log.allow(start + Duration::from_secs(1)));
+}
@@ -0,0 +1,287 @@
+use std::{
+    collections::VecDeque,
+    env,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use toolgun_protocol::{Handshake, Message, PLUGIN_MESSAGE_LENGTH};
+use tracing::{error, info};
+
+/// path to record every received message to
+pub const RECORD_ENV: &str = "TOOLGUN_RECORD";
+/// path of a recording to play back on map load, also works in single player
+pub const REPLAY_ENV: &str = "TOOLGUN_REPLAY";
+
+const MAGIC: [u8; 4] = *b"TGRC";
+const VERSION: u8 = 1;
+
+/// A message and when it arrived, relative to the start of the recording.
+///
+/// On disk: header of `MAGIC` and `VERSION`, then per entry a `u32` of milliseconds
+/// followed by the raw message.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub time: Duration,
+    pub data: [u8; PLUGIN_MESSAGE_LENGTH],
+}
+
+pub fn write_header(data_stream: &mut impl Write) -> Result<()> {
+    data_stream.write_all(&MAGIC)?;
+    data_stream.write_u8(VERSION)?;
+    Ok(())
+}
+
+pub fn write_entry(data_stream: &mut impl Write, time: Duration, data: &[u8]) -> Result<()> {
+    if data.len() != PLUGIN_MESSAGE_LENGTH {
+        bail!("message is {} bytes", data.len());
+    }
+
+    data_stream.write_u32::<NetworkEndian>(time.as_millis().try_into()?)?;
+    data_stream.write_all(data)?;
+    Ok(())
+}
+
+pub fn read_recording(data_stream: &mut impl Read) -> Result<Vec<Entry>> {
+    let mut magic = [0; MAGIC.len()];
+    data_stream.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        bail!("not a recording");
+    }
+    let version = data_stream.read_u8()?;
+    if version != VERSION {
+        bail!("unsupported recording version {}", version);
+    }
+
+    let mut entries = Vec::new();
+    loop {
+        let millis = match data_stream.read_u32::<NetworkEndian>() {
+            Ok(millis) => millis,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+        data_stream.read_exact(&mut data)?;
+
+        entries.push(Entry {
+            time: Duration::from_millis(millis.into()),
+            data,
+        });
+    }
+    Ok(entries)
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_header(&mut writer)?;
+
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let path = env::var_os(RECORD_ENV)?;
+        match Self::create(&path) {
+            Ok(recorder) => {
+                info!("recording to {:?}", path);
+                Some(recorder)
+            }
+
+            Err(e) => {
+                error!("creating recording {:?}: {:#?}", path, e);
+                None
+            }
+        }
+    }
+
+    pub fn record(&mut self, data: &[u8]) -> Result<()> {
+        write_entry(&mut self.writer, self.start.elapsed(), data)?;
+        // flush every time so a crash still leaves a usable recording
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Hands out recorded messages once their original time has passed.
+pub struct Replay {
+    entries: VecDeque<Entry>,
+    start: Instant,
+    /// skip the quiet time before the first message
+    offset: Duration,
+    /// what the recorded server answered, kept apart from the real connection's
+    handshake: Option<Handshake>,
+}
+
+impl Replay {
+    pub fn new(entries: Vec<Entry>, start: Instant) -> Self {
+        let offset = entries.first().map(|entry| entry.time).unwrap_or_default();
+        Self {
+            entries: entries.into(),
+            start,
+            offset,
+            handshake: None,
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let entries = read_recording(&mut BufReader::new(File::open(path)?))?;
+        Ok(Self::new(entries, Instant::now()))
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let path = env::var_os(REPLAY_ENV)?;
+        match Self::open(&path) {
+            Ok(replay) => {
+                info!("replaying {:?}", path);
+                Some(replay)
+            }
+
+            Err(e) => {
+                error!("opening recording {:?}: {:#?}", path, e);
+                None
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// next message that is due at `now`, call until `None`
+    pub fn next_due(&mut self, now: Instant) -> Option<[u8; PLUGIN_MESSAGE_LENGTH]> {
+        let entry = self.entries.front()?;
+        if now.saturating_duration_since(self.start) + self.offset < entry.time {
+            return None;
+        }
+        self.entries.pop_front().map(|entry| entry.data)
+    }
+
+    /// Like `next_due`, but decoded the way the recorded server spoke.
+    ///
+    /// Its handshakes only change how the rest of the recording decodes, they
+    /// aren't returned.
+    pub fn next_message(&mut self, now: Instant) -> Option<Result<Message>> {
+        loop {
+            let data = self.next_due(now)?;
+            match Message::decode(&data, self.handshake) {
+                Ok(Message::Handshake(handshake)) => {
+                    if handshake.is_supported() {
+                        self.handshake = Some(handshake);
+                    }
+                }
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_recording() {
+    use std::io::Cursor;
+
+    let mut file = Vec::new();
+    write_header(&mut file).unwrap();
+    write_entry(
+        &mut file,
+        Duration::from_millis(500),
+        &[1; PLUGIN_MESSAGE_LENGTH],
+    )
+    .unwrap();
+    write_entry(
+        &mut file,
+        Duration::from_millis(2000),
+        &[2; PLUGIN_MESSAGE_LENGTH],
+    )
+    .unwrap();
+    assert!(write_entry(&mut Vec::new(), Duration::ZERO, &[0; 7]).is_err());
+
+    let entries = read_recording(&mut Cursor::new(&file)).unwrap();
+    assert_eq!(
+        entries,
+        [
+            Entry {
+                time: Duration::from_millis(500),
+                data: [1; PLUGIN_MESSAGE_LENGTH],
+            },
+            Entry {
+                time: Duration::from_millis(2000),
+                data: [2; PLUGIN_MESSAGE_LENGTH],
+            },
+        ]
+    );
+
+    let start = Instant::now();
+    let mut replay = Replay::new(entries, start);
+    assert_eq!(replay.next_due(start), Some([1; PLUGIN_MESSAGE_LENGTH]));
+    assert_eq!(replay.next_due(start + Duration::from_secs(1)), None);
+    assert_eq!(
+        replay.next_due(start + Duration::from_secs(2)),
+        Some([2; PLUGIN_MESSAGE_LENGTH])
+    );
+    assert!(replay.is_finished());
+
+    // truncated entry
+    assert!(read_recording(&mut Cursor::new(&file[..file.len() - 1])).is_err());
+}
+
+#[test]
+fn test_replay_messages() {
+    use std::io::Cursor;
+
+    use toolgun_protocol::{BlockPos, CoordEncoding, PROTOCOL_VERSION, Packet, Target};
+
+    let handshake = Handshake {
+        version: PROTOCOL_VERSION,
+        coord_encoding: CoordEncoding::I32,
+        channel: 0,
+    };
+    let target = Target::Block(BlockPos {
+        x: 1,
+        y: -2,
+        z: 300,
+    });
+    let mut file = Vec::new();
+    write_header(&mut file).unwrap();
+    for (millis, message) in [
+        (0, Message::Handshake(handshake)),
+        (
+            100,
+            Message::Packet(Packet {
+                player_id: 7,
+                target,
+            }),
+        ),
+    ] {
+        let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+        message
+            .encode(&mut data.as_mut_slice(), Some(handshake))
+            .unwrap();
+        write_entry(&mut file, Duration::from_millis(millis), &data).unwrap();
+    }
+
+    let entries = read_recording(&mut Cursor::new(&file)).unwrap();
+    let start = Instant::now();
+    let mut replay = Replay::new(entries, start);
+    // the handshake is swallowed, the packet needs it to decode
+    assert!(replay.next_message(start).is_none());
+    let Some(Ok(Message::Packet(decoded))) =
+        replay.next_message(start + Duration::from_millis(100))
+    else {
+        panic!();
+    };
+    assert_eq!(decoded.player_id, 7);
+    assert_eq!(decoded.target, target);
+    assert!(replay.is_finished());
+}
@@ -0,0 +1,347 @@
+use std::time::{Duration, Instant};
+
+use classicube_sys::{
+    Atlas1D, Blocks, DrawType_DRAW_GAS, DrawType_DRAW_SPRITE, DrawType_DRAW_TRANSLUCENT,
+    FACE_CONSTS_FACE_COUNT, FACE_CONSTS_FACE_XMAX, FACE_CONSTS_FACE_XMIN, FACE_CONSTS_FACE_YMAX,
+    FACE_CONSTS_FACE_YMIN, FACE_CONSTS_FACE_ZMAX, FACE_CONSTS_FACE_ZMIN, Gfx_SetAlphaTest,
+    Gfx_SetTexturing, PackedCol, VertexTextured,
+};
+
+use super::{context::vertex_buffer::Gfx_DrawTexturedQuad, render_hook::renderable::Renderable};
+
+/// how long a placed block takes to grow to full size
+pub const GROW_DURATION: Duration = Duration::from_millis(250);
+/// how long a removed block takes to shrink away
+pub const DISSOLVE_DURATION: Duration = Duration::from_millis(300);
+
+/// same as the game's `UV2_Scale`, keeps the next tile from bleeding in
+const UV2_SCALE: f32 = 15.99 / 16.0;
+
+/// A face of the unit cube: which of the block's textures, brightness like the
+/// game shades them, and corners as (x, y, z, u).
+type Face = (u32, u8, [(f32, f32, f32, f32); 4]);
+
+const FACES: [Face; 6] = [
+    (
+        FACE_CONSTS_FACE_XMIN,
+        153,
+        [
+            (0.0, 1.0, 0.0, 0.0),
+            (0.0, 1.0, 1.0, 1.0),
+            (0.0, 0.0, 1.0, 1.0),
+            (0.0, 0.0, 0.0, 0.0),
+        ],
+    ),
+    (
+        FACE_CONSTS_FACE_XMAX,
+        153,
+        [
+            (1.0, 1.0, 1.0, 0.0),
+            (1.0, 1.0, 0.0, 1.0),
+            (1.0, 0.0, 0.0, 1.0),
+            (1.0, 0.0, 1.0, 0.0),
+        ],
+    ),
+    (
+        FACE_CONSTS_FACE_ZMIN,
+        204,
+        [
+            (1.0, 1.0, 0.0, 0.0),
+            (0.0, 1.0, 0.0, 1.0),
+            (0.0, 0.0, 0.0, 1.0),
+            (1.0, 0.0, 0.0, 0.0),
+        ],
+    ),
+    (
+        FACE_CONSTS_FACE_ZMAX,
+        204,
+        [
+            (0.0, 1.0, 1.0, 0.0),
+            (1.0, 1.0, 1.0, 1.0),
+            (1.0, 0.0, 1.0, 1.0),
+            (0.0, 0.0, 1.0, 0.0),
+        ],
+    ),
+    (
+        FACE_CONSTS_FACE_YMIN,
+        128,
+        [
+            (0.0, 0.0, 0.0, 0.0),
+            (1.0, 0.0, 0.0, 1.0),
+            (1.0, 0.0, 1.0, 1.0),
+            (0.0, 0.0, 1.0, 0.0),
+        ],
+    ),
+    (
+        FACE_CONSTS_FACE_YMAX,
+        255,
+        [
+            (0.0, 1.0, 0.0, 0.0),
+            (1.0, 1.0, 0.0, 1.0),
+            (1.0, 1.0, 1.0, 1.0),
+            (0.0, 1.0, 1.0, 0.0),
+        ],
+    ),
+];
+
+/// Whether `block` can be drawn as a cube: not air, not a sprite that'd get its
+/// texture on 6 faces, and not see-through.
+pub fn animates(block: u16) -> bool {
+    let draw = unsafe { Blocks.Draw[usize::from(block)] };
+    ![
+        DrawType_DRAW_GAS,
+        DrawType_DRAW_SPRITE,
+        DrawType_DRAW_TRANSLUCENT,
+    ]
+    .into_iter()
+    .any(|draw_type| draw == draw_type as u8)
+}
+
+/// Where `corner` of `face` ends up for a block with bounds `min..max` shrunk by
+/// `scale` around their center, and its texture coordinates as a fraction of the
+/// tile, cut to the bounds like the game does for slabs.
+fn corner_vertex(
+    face: u32,
+    corner: (f32, f32, f32, f32),
+    min: (f32, f32, f32),
+    max: (f32, f32, f32),
+    scale: f32,
+) -> ((f32, f32, f32), (f32, f32)) {
+    let (x, y, z, u) = corner;
+    let lerp = |from: f32, to: f32, t: f32| from + (to - from) * t;
+    let bounded = (
+        lerp(min.0, max.0, x),
+        lerp(min.1, max.1, y),
+        lerp(min.2, max.2, z),
+    );
+    let position = (
+        lerp((min.0 + max.0) / 2.0, bounded.0, scale),
+        lerp((min.1 + max.1) / 2.0, bounded.1, scale),
+        lerp((min.2 + max.2) / 2.0, bounded.2, scale),
+    );
+
+    // u runs along z on the x faces and along x on the others, either way round
+    let (along, bounded_along) = match face {
+        FACE_CONSTS_FACE_XMIN | FACE_CONSTS_FACE_XMAX => (z, bounded.2),
+        _ => (x, bounded.0),
+    };
+    let u = if u == along {
+        bounded_along
+    } else {
+        1.0 - bounded_along
+    };
+    // down the sides, and from -z to +z on top and bottom
+    let v = match face {
+        FACE_CONSTS_FACE_YMIN | FACE_CONSTS_FACE_YMAX => bounded.2,
+        _ => 1.0 - bounded.1,
+    };
+    (position, (u, v))
+}
+
+/// 0 to 1 over `duration` from `start`
+fn progress(start: Instant, duration: Duration, now: Instant) -> f32 {
+    if duration.is_zero() {
+        return 1.0;
+    }
+    (now.saturating_duration_since(start).as_secs_f32() / duration.as_secs_f32()).min(1.0)
+}
+
+/// fast at first, settling into place
+fn ease_out(t: f32) -> f32 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+/// slow at first, then gone
+fn ease_in(t: f32) -> f32 {
+    t.powi(3)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Grow,
+    Dissolve,
+}
+
+/// A block's textured cube drawn around the center of `pos`, without it being
+/// in the world.
+pub struct BlockAnimation {
+    kind: Kind,
+    pos: (i32, i32, i32),
+    block: u16,
+    start: Instant,
+}
+
+impl BlockAnimation {
+    /// Grows in over `GROW_DURATION`, then stays at full size until dropped.
+    pub fn grow(pos: (i32, i32, i32), block: u16) -> Self {
+        Self {
+            kind: Kind::Grow,
+            pos,
+            block,
+            start: Instant::now(),
+        }
+    }
+
+    /// Shrinks away over `DISSOLVE_DURATION`, for a block that's no longer there.
+    pub fn dissolve(pos: (i32, i32, i32), block: u16) -> Self {
+        Self {
+            kind: Kind::Dissolve,
+            pos,
+            block,
+            start: Instant::now(),
+        }
+    }
+
+    fn scale(&self, now: Instant) -> f32 {
+        match self.kind {
+            Kind::Grow => ease_out(progress(self.start, GROW_DURATION, now)),
+            Kind::Dissolve => 1.0 - ease_in(progress(self.start, DISSOLVE_DURATION, now)),
+        }
+    }
+
+    /// nothing left to draw; growing ones stay until dropped
+    pub fn is_finished(&self, now: Instant) -> bool {
+        self.kind == Kind::Dissolve && self.scale(now) <= 0.0
+    }
+
+    fn render_inner(&self, scale: f32) {
+        if scale <= 0.0 || !animates(self.block) {
+            return;
+        }
+
+        let block = usize::from(self.block);
+        let (min, max) = unsafe { (Blocks.MinBB[block], Blocks.MaxBB[block]) };
+        let (min, max) = ((min.x, min.y, min.z), (max.x, max.y, max.z));
+        let (x, y, z) = self.pos;
+        let (tile_size, shift, mask) =
+            unsafe { (Atlas1D.InvTileSize, Atlas1D.Shift, Atlas1D.Mask) };
+
+        unsafe {
+            Gfx_SetAlphaTest(1);
+            Gfx_SetTexturing(1);
+        }
+        for (face, shade, corners) in FACES {
+            let texture_loc = i32::from(unsafe {
+                Blocks.Textures[block * FACE_CONSTS_FACE_COUNT as usize + face as usize]
+            });
+            let texture_id = unsafe { Atlas1D.TexIds[(texture_loc >> shift) as usize] };
+            let v1 = (texture_loc & mask) as f32 * tile_size;
+            // gray, so the same in any channel order
+            let color = PackedCol::from_ne_bytes([shade, shade, shade, 255]);
+
+            let mut vertices = corners.map(|corner| {
+                let ((corner_x, corner_y, corner_z), (u, v)) =
+                    corner_vertex(face, corner, min, max, scale);
+                VertexTextured {
+                    x: x as f32 + corner_x,
+                    y: y as f32 + corner_y,
+                    z: z as f32 + corner_z,
+                    Col: color,
+                    U: u * UV2_SCALE,
+                    V: v1 + v * UV2_SCALE * tile_size,
+                }
+            });
+            unsafe { Gfx_DrawTexturedQuad(texture_id, &mut vertices) };
+        }
+    }
+}
+
+impl Renderable for BlockAnimation {
+    fn render(&mut self) {
+        self.render_inner(self.scale(Instant::now()));
+    }
+}
+
+#[test]
+fn test_progress() {
+    let start = Instant::now();
+    let duration = Duration::from_millis(200);
+    assert_eq!(progress(start, duration, start), 0.0);
+    assert_eq!(progress(start, duration, start + duration / 2), 0.5);
+    assert_eq!(progress(start, duration, start + duration * 2), 1.0);
+    assert_eq!(progress(start + duration, duration, start), 0.0);
+    assert_eq!(progress(start, Duration::ZERO, start), 1.0);
+
+    assert_eq!(ease_out(0.0), 0.0);
+    assert_eq!(ease_out(1.0), 1.0);
+    assert!(ease_out(0.5) > 0.5);
+    assert_eq!(ease_in(0.0), 0.0);
+    assert_eq!(ease_in(1.0), 1.0);
+    assert!(ease_in(0.5) < 0.5);
+}
+
+#[test]
+fn test_scale() {
+    let grow = BlockAnimation::grow((1, 2, 3), 1);
+    let dissolve = BlockAnimation {
+        start: grow.start,
+        ..BlockAnimation::dissolve((1, 2, 3), 1)
+    };
+    let start = grow.start;
+
+    assert_eq!(grow.scale(start), 0.0);
+    assert_eq!(grow.scale(start + GROW_DURATION), 1.0);
+    assert!(!grow.is_finished(start + GROW_DURATION * 10));
+
+    assert_eq!(dissolve.scale(start), 1.0);
+    assert!(!dissolve.is_finished(start + DISSOLVE_DURATION / 2));
+    assert_eq!(dissolve.scale(start + DISSOLVE_DURATION), 0.0);
+    assert!(dissolve.is_finished(start + DISSOLVE_DURATION));
+}
+
+#[test]
+fn test_faces() {
+    // every corner on its face's side of the cube
+    for (face, _, corners) in FACES {
+        let (axis, side) = match face {
+            FACE_CONSTS_FACE_XMIN => (0, 0.0),
+            FACE_CONSTS_FACE_XMAX => (0, 1.0),
+            FACE_CONSTS_FACE_YMIN => (1, 0.0),
+            FACE_CONSTS_FACE_YMAX => (1, 1.0),
+            FACE_CONSTS_FACE_ZMIN => (2, 0.0),
+            FACE_CONSTS_FACE_ZMAX => (2, 1.0),
+            _ => unreachable!(),
+        };
+        for (x, y, z, _) in corners {
+            assert_eq!([x, y, z][axis], side);
+        }
+    }
+}
+
+#[test]
+fn test_corner_vertex() {
+    let full = ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0));
+    let (face, _, corners) = FACES[0];
+    // the whole tile on a whole block
+    assert_eq!(
+        corner_vertex(face, corners[0], full.0, full.1, 1.0),
+        ((0.0, 1.0, 0.0), (0.0, 0.0))
+    );
+    assert_eq!(
+        corner_vertex(face, corners[2], full.0, full.1, 1.0),
+        ((0.0, 0.0, 1.0), (1.0, 1.0))
+    );
+    // nothing left of it at 0
+    assert_eq!(
+        corner_vertex(face, corners[2], full.0, full.1, 0.0).0,
+        (0.5, 0.5, 0.5)
+    );
+
+    // a slab only shows the bottom half of its side texture, and shrinks around
+    // its own center
+    let slab = ((0.0, 0.0, 0.0), (1.0, 0.5, 1.0));
+    assert_eq!(
+        corner_vertex(face, corners[0], slab.0, slab.1, 1.0),
+        ((0.0, 0.5, 0.0), (0.0, 0.5))
+    );
+    assert_eq!(
+        corner_vertex(face, corners[0], slab.0, slab.1, 0.5),
+        ((0.25, 0.375, 0.25), (0.0, 0.5))
+    );
+    // and the whole of its top
+    let (face, _, corners) = FACES[5];
+    assert_eq!(
+        corner_vertex(face, corners[2], slab.0, slab.1, 1.0),
+        ((1.0, 0.5, 1.0), (1.0, 1.0))
+    );
+}
@@ -4,8 +4,9 @@ use std::cell::RefCell;
 
 use classicube_helpers::WithInner;
 use classicube_sys::{
-    Gfx_BindTexture, Gfx_SetVertexFormat, Gfx_UpdateDynamicVb_IndexedTris, OwnedGfxVertexBuffer,
-    PACKEDCOL_WHITE, PackedCol, Texture, VertexFormat__VERTEX_FORMAT_TEXTURED, VertexTextured,
+    Gfx_BindTexture, Gfx_SetVertexFormat, Gfx_UpdateDynamicVb_IndexedTris, GfxResourceID,
+    OwnedGfxVertexBuffer, PACKEDCOL_WHITE, PackedCol, Texture,
+    VertexFormat__VERTEX_FORMAT_TEXTURED, VertexTextured,
 };
 use tracing::warn;
 
@@ -35,6 +36,22 @@ pub unsafe fn Texture_Render(tex: &mut Texture, front: bool) {
     }
 }
 
+/// any 4 corners, with the current matrices
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn Gfx_DrawTexturedQuad(texture_id: GfxResourceID, vertices: &mut [VertexTextured; 4]) {
+    unsafe {
+        Gfx_BindTexture(texture_id);
+        Gfx_SetVertexFormat(VertexFormat__VERTEX_FORMAT_TEXTURED);
+    }
+    TEX_VB
+        .with_inner(|tex_vb| unsafe {
+            Gfx_UpdateDynamicVb_IndexedTris(tex_vb.resource_id, vertices.as_mut_ptr() as _, 4);
+        })
+        .unwrap_or_else(|| {
+            warn!("TEX_VB None");
+        });
+}
+
 pub fn context_recreated() {
     TEX_VB.with_borrow_mut(|tex_vb| {
         // create texture buffer
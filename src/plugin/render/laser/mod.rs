@@ -13,8 +13,13 @@ use nalgebra::IsometryMatrix3;
 use nalgebra::{Matrix3, Matrix4, Point3, Rotation3, Vector3, center, distance};
 use nalgebra_glm::{identity, scale, translate};
 use texture::create_texture;
+use toolgun_protocol::Target;
 
-use super::{context::vertex_buffer::Texture_Render, render_hook::renderable::Renderable};
+use super::{
+    context::vertex_buffer::Texture_Render, get_target_position,
+    render_hook::renderable::Renderable,
+};
+use crate::plugin::effect::Effect;
 
 pub fn vec3_to_point3(v: &Vec3) -> Point3<f32> {
     Point3::new(v.x, v.y, v.z)
@@ -26,23 +31,45 @@ pub fn vec3_to_vector3(v: &Vec3) -> Vector3<f32> {
 
 pub struct Laser {
     start_pos: Vec3,
+    target: Target,
     end_pos: Vec3,
+    /// length the texture's uv was made for
+    texture_block_width: f32,
     texture: OwnedTexture,
 }
 
 impl Laser {
-    pub fn new(start_pos: Vec3, end_pos: Vec3) -> Self {
+    pub fn new(start_pos: Vec3, target: Target, end_pos: Vec3, effect: Effect) -> Self {
         let block_width = (end_pos - start_pos).length_squared().sqrt();
-        let texture = create_texture(block_width);
+        let texture = create_texture(block_width, effect.tint());
 
         Self {
             start_pos,
+            target,
             end_pos,
+            texture_block_width: block_width,
             texture,
         }
     }
 
+    /// keep following a moving target; if it's gone, stay where it was last seen
+    fn update_end_pos(&mut self) {
+        let Some(end_pos) = get_target_position(&self.target) else {
+            return;
+        };
+        self.end_pos = end_pos;
+
+        // u2 grows linearly with length, so rescale instead of making a new texture
+        let block_width = (self.end_pos - self.start_pos).length_squared().sqrt();
+        if self.texture_block_width > 0.0 {
+            self.texture.as_texture_mut().uv.u2 *= block_width / self.texture_block_width;
+            self.texture_block_width = block_width;
+        }
+    }
+
     fn render_inner(&mut self) {
+        self.update_end_pos();
+
         let start_pos = vec3_to_point3(&self.start_pos);
         let end_pos = vec3_to_point3(&self.end_pos);
         let block_width = distance(&start_pos, &end_pos);
@@ -1,27 +1,100 @@
-use std::os::raw::c_int;
+use std::{borrow::Cow, cell::RefCell, io::Cursor, os::raw::c_int};
 
+use anyhow::{Result, bail};
 use classicube_sys::{
-    Bitmap, Context2D, Context2D_DrawPixels, OwnedContext2D, OwnedTexture, TextureRec, cc_int16,
+    Bitmap, Context2D, Context2D_DrawPixels, OwnedContext2D, OwnedTexture, PackedCol, TextureRec,
+    cc_int16,
 };
-use tracing::{debug, warn};
+use toolgun_protocol::ImageData;
+use tracing::debug;
 
-use crate::textures::{LIGHTNING_FRAME_HEIGHT, LIGHTNING_FRAME_PIXELS, LIGHTNING_FRAME_WIDTH};
+use crate::{
+    color::color,
+    textures::{LIGHTNING_FRAME_HEIGHT, LIGHTNING_FRAME_PIXELS, LIGHTNING_FRAME_WIDTH},
+};
 
 const BLOCK_WIDTH: f32 = 16.0;
 
+/// server textures bigger than this are rejected
+pub const MAX_TEXTURE_WIDTH: u32 = 512;
+pub const MAX_TEXTURE_HEIGHT: u32 = 64;
+/// for the png decoder's own buffers, on top of the pixels
+const PNG_DECODE_LIMIT: usize = 1024 * 1024;
+
+pub struct BeamTexture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<PackedCol>,
+}
+
+thread_local!(
+    static SERVER_TEXTURE: RefCell<Option<BeamTexture>> = Default::default();
+);
+
+/// Use `texture` for new beams instead of the built-in one, `None` to go back.
+pub fn set_server_texture(texture: Option<BeamTexture>) {
+    SERVER_TEXTURE.set(texture);
+}
+
+/// Multiply every channel of `pixels` by `tint`'s; both in the same channel
+/// order, whatever it is.
+fn tint_pixels(pixels: &[PackedCol], tint: PackedCol) -> Vec<PackedCol> {
+    let tint = tint.to_ne_bytes();
+    pixels
+        .iter()
+        .map(|pixel| {
+            let mut bytes = pixel.to_ne_bytes();
+            for (byte, tint) in bytes.iter_mut().zip(tint) {
+                *byte = (u16::from(*byte) * u16::from(tint) / 255) as u8;
+            }
+            PackedCol::from_ne_bytes(bytes)
+        })
+        .collect()
+}
+
+/// `pixels` tinted, or as they are for white, which wouldn't change them.
+fn tinted(pixels: &[PackedCol], tint: (u8, u8, u8)) -> Cow<'_, [PackedCol]> {
+    if tint == (255, 255, 255) {
+        return Cow::Borrowed(pixels);
+    }
+    Cow::Owned(tint_pixels(pixels, color(tint.0, tint.1, tint.2, 255)))
+}
+
 /// returns (front, back)
 #[tracing::instrument]
-pub fn create_texture(block_width: f32) -> OwnedTexture {
+pub fn create_texture(block_width: f32, tint: (u8, u8, u8)) -> OwnedTexture {
     debug!("");
 
+    SERVER_TEXTURE.with_borrow(|option| match option {
+        Some(texture) => create_texture_from(
+            block_width,
+            texture.width,
+            texture.height,
+            &tinted(&texture.pixels, tint),
+        ),
+        None => create_texture_from(
+            block_width,
+            LIGHTNING_FRAME_WIDTH,
+            LIGHTNING_FRAME_HEIGHT,
+            &tinted(&LIGHTNING_FRAME_PIXELS, tint),
+        ),
+    })
+}
+
+fn create_texture_from(
+    block_width: f32,
+    width: u32,
+    height: u32,
+    pixels: &[PackedCol],
+) -> OwnedTexture {
     let (mut context_2d, width, height) = unsafe {
-        let width = LIGHTNING_FRAME_WIDTH as c_int;
-        let height = LIGHTNING_FRAME_HEIGHT as c_int;
+        let width = width as c_int;
+        let height = height as c_int;
         debug!(?width, ?height);
 
         let mut context_2d = OwnedContext2D::new_pow_of_2(width, height, 0x0000_0000);
 
-        draw_parts(context_2d.as_context_2d_mut(), width, height);
+        draw_parts(context_2d.as_context_2d_mut(), width, height, pixels);
 
         (context_2d, width, height)
     };
@@ -43,8 +116,8 @@ pub fn create_texture(block_width: f32) -> OwnedTexture {
     )
 }
 
-unsafe fn draw_parts(context: &mut Context2D, width: c_int, height: c_int) {
-    let mut lightning_frame_width = LIGHTNING_FRAME_PIXELS.to_vec();
+unsafe fn draw_parts(context: &mut Context2D, width: c_int, height: c_int, pixels: &[PackedCol]) {
+    let mut lightning_frame_width = pixels.to_vec();
     // lightning_frame_width[0] = PackedCol_Make(255, 0, 0, 255);
     // lightning_frame_width[1] = PackedCol_Make(0, 255, 0, 255);
     // lightning_frame_width[2] = PackedCol_Make(0, 0, 255, 255);
@@ -64,3 +137,145 @@ unsafe fn draw_parts(context: &mut Context2D, width: c_int, height: c_int) {
         );
     }
 }
+
+fn check_size(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 || width > MAX_TEXTURE_WIDTH || height > MAX_TEXTURE_HEIGHT {
+        bail!(
+            "texture is {}x{}, must be at most {}x{}",
+            width,
+            height,
+            MAX_TEXTURE_WIDTH,
+            MAX_TEXTURE_HEIGHT
+        );
+    }
+    Ok(())
+}
+
+/// returns (width, height, rgba)
+fn decode_rgba(image: &ImageData) -> Result<(u32, u32, Vec<u8>)> {
+    match image {
+        ImageData::Rgba {
+            width,
+            height,
+            pixels,
+        } => {
+            let (width, height) = (u32::from(*width), u32::from(*height));
+            check_size(width, height)?;
+            Ok((width, height, pixels.clone()))
+        }
+
+        ImageData::Png(png) => {
+            let mut decoder = png::Decoder::new_with_limits(
+                Cursor::new(png),
+                png::Limits {
+                    bytes: PNG_DECODE_LIMIT,
+                },
+            );
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+            let mut reader = decoder.read_info()?;
+            // before allocating anything for the pixels
+            check_size(reader.info().width, reader.info().height)?;
+
+            let Some(buffer_size) = reader.output_buffer_size() else {
+                bail!("png too big");
+            };
+            let mut buf = vec![0; buffer_size];
+            let info = reader.next_frame(&mut buf)?;
+            let bytes = &buf[..info.buffer_size()];
+
+            let rgba = match info.color_type {
+                png::ColorType::Rgba => bytes.to_vec(),
+                png::ColorType::Rgb => bytes
+                    .chunks(3)
+                    .flat_map(|c| [c[0], c[1], c[2], 255])
+                    .collect(),
+                png::ColorType::GrayscaleAlpha => bytes
+                    .chunks(2)
+                    .flat_map(|c| [c[0], c[0], c[0], c[1]])
+                    .collect(),
+                png::ColorType::Grayscale => bytes.iter().flat_map(|&c| [c, c, c, 255]).collect(),
+                other => bail!("unsupported ColorType {:?}", other),
+            };
+            Ok((info.width, info.height, rgba))
+        }
+    }
+}
+
+/// Validate and convert a texture sent by the server.
+pub fn decode_beam_texture(image: &ImageData) -> Result<BeamTexture> {
+    let (width, height, rgba) = decode_rgba(image)?;
+
+    let pixels = rgba
+        .chunks(4)
+        .map(|c| color(c[0], c[1], c[2], c[3]))
+        .collect::<Vec<_>>();
+    if pixels.len() != (width * height) as usize {
+        bail!(
+            "texture has {} pixels instead of {}x{}",
+            pixels.len(),
+            width,
+            height
+        );
+    }
+
+    Ok(BeamTexture {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[test]
+fn test_tint_pixels() {
+    let pixels = [
+        PackedCol::from_ne_bytes([255, 255, 255, 255]),
+        PackedCol::from_ne_bytes([100, 200, 50, 128]),
+    ];
+    let tint = PackedCol::from_ne_bytes([255, 0, 51, 255]);
+    assert_eq!(
+        tint_pixels(&pixels, tint),
+        [
+            PackedCol::from_ne_bytes([255, 0, 51, 255]),
+            PackedCol::from_ne_bytes([100, 0, 10, 128]),
+        ]
+    );
+
+    assert!(matches!(tinted(&pixels, (255, 255, 255)), Cow::Borrowed(_)));
+    assert!(matches!(tinted(&pixels, (255, 0, 51)), Cow::Owned(_)));
+}
+
+#[test]
+fn test_decode_rgba() {
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 0, 0, 0, 0, 255]).unwrap();
+    }
+    let (width, height, rgba) = decode_rgba(&ImageData::Png(png)).unwrap();
+    assert_eq!((width, height), (2, 1));
+    assert_eq!(rgba, [255, 0, 0, 255, 0, 0, 255, 255]);
+
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, MAX_TEXTURE_WIDTH + 1, 1);
+        encoder.set_color(png::ColorType::Grayscale);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[0; MAX_TEXTURE_WIDTH as usize + 1])
+            .unwrap();
+    }
+    assert!(decode_rgba(&ImageData::Png(png)).is_err());
+
+    assert!(decode_rgba(&ImageData::Png(vec![1, 2, 3])).is_err());
+    assert!(
+        decode_rgba(&ImageData::Rgba {
+            width: 0,
+            height: 1,
+            pixels: Vec::new(),
+        })
+        .is_err()
+    );
+}
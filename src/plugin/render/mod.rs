@@ -1,19 +1,22 @@
+pub mod block_animation;
 pub mod context;
 pub mod laser;
 pub mod render_hook;
 
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, rc::Rc, time::Instant};
 
-use classicube_helpers::entities::Entities;
-use classicube_sys::{IVec3, Vec3};
-use tracing::debug;
+use classicube_helpers::{entities::Entities, tick::TickEventHandler};
+use classicube_sys::{BlockID, IVec3, Particles_BreakBlockEffect, Vec3};
+use toolgun_protocol::Target;
+use tracing::{debug, warn};
 
 use self::{
+    block_animation::{BlockAnimation, animates},
     context::ContextModule,
     laser::Laser,
     render_hook::{RenderHookModule, renderable::StartStopRendering},
 };
-use crate::plugin::module::Module;
+use crate::plugin::{effect::Effect, module::Module};
 
 thread_local!(
     static ENTITIES: RefCell<Option<Entities>> = Default::default();
@@ -23,9 +26,17 @@ thread_local!(
     static LASERS: RefCell<Vec<Rc<RefCell<Laser>>>> = Default::default();
 );
 
+/// blocks dissolving at once, the rest of a mass delete just disappears
+const MAX_DISSOLVING: usize = 1024;
+
+thread_local!(
+    static DISSOLVING: RefCell<Vec<Rc<RefCell<BlockAnimation>>>> = Default::default();
+);
+
 pub struct RenderModule {
     context_module: ContextModule,
     render_hook_module: RenderHookModule,
+    _tick_handler: TickEventHandler,
 }
 
 impl RenderModule {
@@ -38,9 +49,19 @@ impl RenderModule {
             *option = Some(entities);
         });
 
+        let mut tick_handler = TickEventHandler::new();
+        tick_handler.on(move |_event| {
+            // done shrinking, nothing left to draw
+            let now = Instant::now();
+            DISSOLVING.with_borrow_mut(|dissolving| {
+                dissolving.retain(|animation| !animation.borrow().is_finished(now));
+            });
+        });
+
         Self {
             context_module,
             render_hook_module,
+            _tick_handler: tick_handler,
         }
     }
 }
@@ -50,6 +71,11 @@ impl Module for RenderModule {
         vec![&mut self.context_module, &mut self.render_hook_module]
     }
 
+    fn reset(&mut self) {
+        // textures are per server
+        laser::texture::set_server_texture(None);
+    }
+
     fn free(&mut self) {
         ENTITIES.with_borrow_mut(|option| {
             drop(option.take());
@@ -57,31 +83,92 @@ impl Module for RenderModule {
         LASERS.with_borrow_mut(|lasers| {
             lasers.clear();
         });
+        DISSOLVING.with_borrow_mut(|dissolving| {
+            dissolving.clear();
+        });
+    }
+}
+
+/// Where `target` currently is in the world, `None` if it's an entity that doesn't exist.
+pub fn get_target_position(target: &Target) -> Option<Vec3> {
+    match *target {
+        Target::Block(block_pos) => Some(Vec3 {
+            x: block_pos.x as f32 + 0.5,
+            y: block_pos.y as f32 + 0.5,
+            z: block_pos.z as f32 + 0.5,
+        }),
+
+        Target::Entity(entity_id) => ENTITIES.with_borrow(|option| {
+            let entity = option.as_ref()?.get(entity_id)?.upgrade()?;
+            // aim for the middle of the body instead of the feet
+            let feet_pos = entity.get_position();
+            let eye_pos = entity.get_eye_position();
+            Some(Vec3 {
+                x: feet_pos.x,
+                y: (feet_pos.y + eye_pos.y) / 2.0,
+                z: feet_pos.z,
+            })
+        }),
+
+        Target::Point(pos) => Some(Vec3 {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z,
+        }),
     }
 }
 
 #[tracing::instrument]
-pub fn create_laser(entity_id: u8, block_pos: IVec3) {
+pub fn create_laser(entity_id: u8, target: Target, effect: Effect) {
     debug!("");
 
-    let player_pos = ENTITIES.with_borrow(|option| {
-        option
-            .as_ref()
-            .unwrap()
-            .get(entity_id)
-            .unwrap()
-            .upgrade()
-            .unwrap()
-            .get_eye_position()
-    });
-    let block_pos = Vec3 {
-        x: block_pos.x as f32 + 0.5,
-        y: block_pos.y as f32 + 0.5,
-        z: block_pos.z as f32 + 0.5,
+    let Some(player_pos) = ENTITIES.with_borrow(|option| {
+        Some(
+            option
+                .as_ref()?
+                .get(entity_id)?
+                .upgrade()?
+                .get_eye_position(),
+        )
+    }) else {
+        warn!("no entity {}", entity_id);
+        return;
+    };
+    let Some(end_pos) = get_target_position(&target) else {
+        warn!("no position for {:?}", target);
+        return;
     };
     LASERS.with_borrow_mut(|lasers| {
-        let laser = Rc::new(RefCell::new(Laser::new(player_pos, block_pos)));
+        let laser = Rc::new(RefCell::new(Laser::new(
+            player_pos, target, end_pos, effect,
+        )));
         laser.start_rendering();
         lasers.push(laser);
     })
 }
+
+/// Pieces of `old_block` flying off, like when you break one yourself.
+pub fn spawn_debris(x: i32, y: i32, z: i32, old_block: BlockID) {
+    unsafe {
+        // the game only makes debris for blocks broken to air
+        Particles_BreakBlockEffect(IVec3 { x, y, z }, old_block, 0);
+    }
+}
+
+/// `block` growing in at `pos`, drawn until the returned handle is dropped.
+pub fn grow_block(pos: (i32, i32, i32), block: u16) -> Rc<RefCell<BlockAnimation>> {
+    let animation = Rc::new(RefCell::new(BlockAnimation::grow(pos, block)));
+    animation.start_rendering();
+    animation
+}
+
+/// `block` shrinking away at `pos`, where it was just removed; dropped on the
+/// first tick after it's gone.
+pub fn dissolve_block(pos: (i32, i32, i32), block: u16) {
+    if !animates(block) || DISSOLVING.with_borrow(|dissolving| dissolving.len()) >= MAX_DISSOLVING {
+        return;
+    }
+    let animation = Rc::new(RefCell::new(BlockAnimation::dissolve(pos, block)));
+    animation.start_rendering();
+    DISSOLVING.with_borrow_mut(|dissolving| dissolving.push(animation));
+}
@@ -1,10 +1,10 @@
 use std::{cell::RefCell, collections::VecDeque, io::Cursor};
 
 use classicube_helpers::{entities::ENTITY_SELF_ID, tick::TickEventHandler};
-use classicube_sys::{Entities, IVec3};
-use rodio::{Decoder, DeviceSinkBuilder, MixerDeviceSink, SpatialPlayer};
+use classicube_sys::{Entities, Vec3};
+use rodio::{Decoder, DeviceSinkBuilder, MixerDeviceSink, Source, SpatialPlayer};
 
-use crate::plugin::module::Module;
+use crate::plugin::{effect::Effect, module::Module};
 
 const MAX_SINKS: usize = 100;
 
@@ -15,17 +15,15 @@ thread_local!(
         Default::default();
 );
 
-pub fn play_sound(block_pos: IVec3) {
+pub fn play_sound(pos: Vec3, effect: Effect) {
     let (left_ear_pos, right_ear_pos) = get_sink_ear_positions();
-    let emitter_pos = [
-        (block_pos.x as f32) + 0.5,
-        (block_pos.y as f32) + 0.5,
-        (block_pos.z as f32) + 0.5,
-    ];
+    let emitter_pos = [pos.x, pos.y, pos.z];
 
     RODIO_STREAM.with_borrow_mut(|option| {
         if let Some((device_sink, sinks)) = option.as_mut() {
-            let source = Decoder::new(Cursor::new(TOOLGUN_BYTES)).unwrap();
+            let source = Decoder::new(Cursor::new(TOOLGUN_BYTES))
+                .unwrap()
+                .speed(effect.sound_speed());
             let sink = SpatialPlayer::connect_new(
                 device_sink.mixer(),
                 emitter_pos,
@@ -0,0 +1,77 @@
+use toolgun_protocol::{
+    CoordEncoding, ImageData, LargeMessage, Message, Target, fragment::FRAGMENT_PAYLOAD_LENGTH,
+};
+
+fn describe_target(target: &Target) -> String {
+    match target {
+        Target::Block(block_pos) => {
+            format!("block ({}, {}, {})", block_pos.x, block_pos.y, block_pos.z)
+        }
+        Target::Entity(entity_id) => format!("entity {}", entity_id),
+        Target::Point(pos) => format!("point ({}, {}, {})", pos.x, pos.y, pos.z),
+    }
+}
+
+pub fn describe_message(message: &Message) -> String {
+    match message {
+        Message::Handshake(handshake) => format!(
+            "handshake v{}, {} coords, channel {}",
+            handshake.version,
+            match handshake.coord_encoding {
+                CoordEncoding::U16 => "u16",
+                CoordEncoding::I32 => "i32",
+            },
+            handshake.channel
+        ),
+
+        Message::Packet(packet) => format!(
+            "player {} fired at {}",
+            packet.player_id,
+            describe_target(&packet.target)
+        ),
+
+        Message::Fragment(fragment) => format!(
+            "fragment {} of {} of message {}, {} of {} bytes",
+            usize::from(fragment.sequence) + 1,
+            usize::from(fragment.total_length)
+                .div_ceil(FRAGMENT_PAYLOAD_LENGTH)
+                .max(1),
+            fragment.message_id,
+            fragment.payload.len(),
+            fragment.total_length
+        ),
+    }
+}
+
+pub fn describe_large_message(message: &LargeMessage) -> String {
+    match message {
+        LargeMessage::BeamTexture(ImageData::Png(png)) => {
+            format!("beam texture, {} bytes of png", png.len())
+        }
+        LargeMessage::BeamTexture(ImageData::Rgba { width, height, .. }) => {
+            format!("beam texture, {}x{} rgba", width, height)
+        }
+    }
+}
+
+#[test]
+fn test_describe() {
+    use toolgun_protocol::{BlockPos, Fragment, Packet};
+
+    assert_eq!(
+        describe_message(&Message::Packet(Packet {
+            player_id: 3,
+            target: Target::Block(BlockPos { x: 1, y: -2, z: 3 }),
+        })),
+        "player 3 fired at block (1, -2, 3)"
+    );
+
+    let fragments = Fragment::split(5, &[0; FRAGMENT_PAYLOAD_LENGTH + 1]).unwrap();
+    assert_eq!(
+        describe_message(&Message::Fragment(fragments[1].clone())),
+        format!(
+            "fragment 2 of 2 of message 5, 1 of {} bytes",
+            FRAGMENT_PAYLOAD_LENGTH + 1
+        )
+    );
+}
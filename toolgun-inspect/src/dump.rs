@@ -0,0 +1,68 @@
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use toolgun_protocol::PLUGIN_MESSAGE_LENGTH;
+
+/// Hex (with or without spaces and colons) or base64, padded with zeroes to a
+/// full plugin message so dumps with the trailing zeroes cut off still decode.
+///
+/// Anything that is valid hex is read as hex.
+pub fn parse_dump(dump: &str) -> Result<[u8; PLUGIN_MESSAGE_LENGTH]> {
+    let dump = dump.trim();
+    let hex = dump
+        .strip_prefix("0x")
+        .unwrap_or(dump)
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect::<String>();
+
+    let bytes = if hex.len() % 2 == 0 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        STANDARD
+            .decode(dump)
+            .with_context(|| format!("{:?} is neither hex nor base64", dump))?
+    };
+
+    if bytes.len() > PLUGIN_MESSAGE_LENGTH {
+        bail!(
+            "{} bytes, plugin messages are only {}",
+            bytes.len(),
+            PLUGIN_MESSAGE_LENGTH
+        );
+    }
+    let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+    data[..bytes.len()].copy_from_slice(&bytes);
+    Ok(data)
+}
+
+pub fn format_hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn format_base64(data: &[u8]) -> String {
+    STANDARD.encode(data)
+}
+
+#[test]
+fn test_parse_dump() {
+    let data = parse_dump("07 00:01 0x").unwrap_err();
+    assert!(data.to_string().contains("neither"));
+
+    let data = parse_dump("0x0700 01\n").unwrap();
+    assert_eq!(data[..4], [7, 0, 1, 0]);
+
+    let data = parse_dump(&format_base64(&[0xFF; 5])).unwrap();
+    assert_eq!(data[..6], [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0]);
+
+    assert_eq!(
+        parse_dump(&format_hex(&[1; PLUGIN_MESSAGE_LENGTH])).unwrap(),
+        [1; PLUGIN_MESSAGE_LENGTH]
+    );
+    assert!(parse_dump(&format_hex(&[1; PLUGIN_MESSAGE_LENGTH + 1])).is_err());
+}
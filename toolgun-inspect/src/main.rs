@@ -0,0 +1,228 @@
+mod describe;
+mod dump;
+
+use std::{
+    env,
+    io::{self, BufRead},
+    time::Instant,
+};
+
+use anyhow::{Context, Result, bail};
+use toolgun_protocol::{
+    BlockPos, CoordEncoding, DEFAULT_CHANNEL, Fragment, Handshake, LargeMessage, Message,
+    PLUGIN_MESSAGE_LENGTH, PROTOCOL_VERSION, Packet, Point, Reassembler, Target,
+};
+
+use self::{
+    describe::{describe_large_message, describe_message},
+    dump::{format_base64, format_hex, parse_dump},
+};
+
+const USAGE: &str = "usage:
+  toolgun-inspect decode [options] [dump...]
+  toolgun-inspect encode [options] <json>
+  toolgun-inspect encode [options] handshake [u16|i32] [channel]
+  toolgun-inspect encode [options] packet <player id> block <x> <y> <z>
+  toolgun-inspect encode [options] packet <player id> entity <entity id>
+  toolgun-inspect encode [options] packet <player id> point <x> <y> <z>
+
+Dumps are hex (spaces and colons allowed) or base64, one plugin message each,
+read from stdin one per line if none are given. Fragments are reassembled across
+dumps, and a handshake switches the dumps after it to its coord encoding, like
+the plugin does.
+
+JSON is a message as printed by `decode --json`, e.g.
+  {\"packet\":{\"player_id\":1,\"target\":{\"entity\":4}}}
+or a large message, which is split into fragments, e.g.
+  {\"beam_texture\":{\"png\":[137,80,78,71]}}
+
+options:
+  --legacy   no handshake yet: no opcodes, block targets with u16 coords only
+  --u16      the handshake picked u16 coords (default i32)
+  --json     decode to JSON instead of text
+  --base64   encode to base64 instead of hex";
+
+struct Options {
+    handshake: Option<Handshake>,
+    json: bool,
+    base64: bool,
+}
+
+fn negotiated(coord_encoding: CoordEncoding) -> Option<Handshake> {
+    Some(Handshake {
+        version: PROTOCOL_VERSION,
+        coord_encoding,
+        channel: DEFAULT_CHANNEL,
+    })
+}
+
+fn parse<T>(args: &mut impl Iterator<Item = String>, what: &str) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let arg = args.next().with_context(|| format!("missing {what}"))?;
+    arg.parse().with_context(|| format!("bad {what} {arg:?}"))
+}
+
+fn decode(dumps: Vec<String>, options: &mut Options) -> Result<()> {
+    let dumps = if dumps.is_empty() {
+        io::stdin().lock().lines().collect::<Result<Vec<_>, _>>()?
+    } else {
+        dumps
+    };
+
+    let mut reassembler = Reassembler::default();
+    for dump in dumps.iter().filter(|dump| !dump.trim().is_empty()) {
+        let data = parse_dump(dump)?;
+        let message = match Message::decode(&data, options.handshake) {
+            Ok(message) => message,
+            Err(e) => {
+                println!("error: {e:#}");
+                continue;
+            }
+        };
+
+        if options.json {
+            println!("{}", serde_json::to_string(&message)?);
+        } else {
+            println!("{}", describe_message(&message));
+        }
+
+        match message {
+            Message::Handshake(handshake) => options.handshake = Some(handshake),
+
+            Message::Fragment(fragment) => {
+                let large_message = match reassembler.receive(fragment, Instant::now()) {
+                    Ok(Some(data)) => LargeMessage::decode(&data),
+                    Ok(None) => continue,
+                    Err(e) => Err(e),
+                };
+                match large_message {
+                    Ok(large_message) if options.json => {
+                        println!("{}", serde_json::to_string(&large_message)?);
+                    }
+                    Ok(large_message) => {
+                        println!("  -> {}", describe_large_message(&large_message));
+                    }
+                    Err(e) => println!("  -> error: {e:#}"),
+                }
+            }
+
+            Message::Packet(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn encode_args(mut args: impl Iterator<Item = String>) -> Result<Vec<Message>> {
+    let Some(first) = args.next() else {
+        bail!("nothing to encode\n\n{USAGE}");
+    };
+
+    let message = match first.as_str() {
+        "handshake" => {
+            let coord_encoding = match args.next().as_deref() {
+                None | Some("i32") => CoordEncoding::I32,
+                Some("u16") => CoordEncoding::U16,
+                Some(other) => bail!("unknown coord encoding {other:?}"),
+            };
+            let channel = match args.next() {
+                Some(channel) => channel.parse().context("bad channel")?,
+                None => DEFAULT_CHANNEL,
+            };
+            Message::Handshake(Handshake {
+                version: PROTOCOL_VERSION,
+                coord_encoding,
+                channel,
+            })
+        }
+
+        "packet" => {
+            let player_id = parse(&mut args, "player id")?;
+            let target = match args.next().as_deref() {
+                Some("block") => Target::Block(BlockPos {
+                    x: parse(&mut args, "x")?,
+                    y: parse(&mut args, "y")?,
+                    z: parse(&mut args, "z")?,
+                }),
+                Some("entity") => Target::Entity(parse(&mut args, "entity id")?),
+                Some("point") => Target::Point(Point {
+                    x: parse(&mut args, "x")?,
+                    y: parse(&mut args, "y")?,
+                    z: parse(&mut args, "z")?,
+                }),
+                other => bail!("unknown target {other:?}"),
+            };
+            Message::Packet(Packet { player_id, target })
+        }
+
+        json => {
+            if let Ok(message) = serde_json::from_str::<Message>(json) {
+                message
+            } else {
+                let large_message = serde_json::from_str::<LargeMessage>(json)
+                    .context("not a message or a large message")?;
+                let mut data = Vec::new();
+                large_message.encode(&mut data)?;
+                return Ok(Fragment::split(0, &data)?
+                    .into_iter()
+                    .map(Message::Fragment)
+                    .collect());
+            }
+        }
+    };
+
+    if let Some(extra) = args.next() {
+        bail!("unexpected {extra:?}");
+    }
+    Ok(vec![message])
+}
+
+fn encode(args: Vec<String>, options: &Options) -> Result<()> {
+    for message in encode_args(args.into_iter())? {
+        let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+        message.encode(&mut data.as_mut_slice(), options.handshake)?;
+        if options.base64 {
+            println!("{}", format_base64(&data));
+        } else {
+            println!("{}", format_hex(&data));
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut options = Options {
+        handshake: negotiated(CoordEncoding::I32),
+        json: false,
+        base64: false,
+    };
+
+    // only `--` flags, so negative coords aren't mistaken for options
+    let mut args = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--legacy" => options.handshake = None,
+            "--u16" => options.handshake = negotiated(CoordEncoding::U16),
+            "--json" => options.json = true,
+            "--base64" => options.base64 = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if arg.starts_with("--") => bail!("unknown option {arg:?}\n\n{USAGE}"),
+            _ => args.push(arg),
+        }
+    }
+
+    if args.is_empty() {
+        bail!("{USAGE}");
+    }
+    let command = args.remove(0);
+    match command.as_str() {
+        "decode" => decode(args, &mut options),
+        "encode" => encode(args, &options),
+        _ => bail!("unknown command {command:?}\n\n{USAGE}"),
+    }
+}
@@ -0,0 +1,289 @@
+use std::io::{Read, Write};
+
+use anyhow::{Result, bail};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use toolgun_protocol::PLUGIN_MESSAGE_LENGTH;
+
+// just enough of https://wiki.vg/Classic_Protocol and https://wiki.vg/Classic_Protocol_Extension
+
+pub const PROTOCOL_VERSION: u8 = 7;
+/// in the unused byte of `PlayerIdentification` when the client speaks CPE
+pub const CPE_MAGIC: u8 = 0x42;
+pub const STRING_LENGTH: usize = 64;
+pub const LEVEL_CHUNK_LENGTH: usize = 1024;
+/// blocks per `BulkBlockUpdate`
+pub const BULK_BLOCK_COUNT: usize = 256;
+/// 51/32 blocks, between the feet and the position players are sent at
+const EYE_HEIGHT: f32 = 1.59375;
+/// entity id meaning "you" in `SpawnPlayer`
+pub const SELF_ID: u8 = 255;
+
+pub const PLUGIN_MESSAGES_EXT: &str = "PluginMessages";
+pub const BULK_BLOCK_UPDATE_EXT: &str = "BulkBlockUpdate";
+
+const IDENTIFICATION: u8 = 0x00;
+const PING: u8 = 0x01;
+const LEVEL_INITIALIZE: u8 = 0x02;
+const LEVEL_DATA_CHUNK: u8 = 0x03;
+const LEVEL_FINALIZE: u8 = 0x04;
+const SET_BLOCK_CLIENT: u8 = 0x05;
+const SET_BLOCK: u8 = 0x06;
+const SPAWN_PLAYER: u8 = 0x07;
+const POSITION: u8 = 0x08;
+const MESSAGE: u8 = 0x0D;
+const EXT_INFO: u8 = 0x10;
+const EXT_ENTRY: u8 = 0x11;
+const BULK_BLOCK_UPDATE: u8 = 0x26;
+const PLUGIN_MESSAGE: u8 = 0x35;
+
+pub fn read_string(data_stream: &mut impl Read) -> Result<String> {
+    let mut data = [0; STRING_LENGTH];
+    data_stream.read_exact(&mut data)?;
+    Ok(String::from_utf8_lossy(&data).trim_end().to_string())
+}
+
+pub fn write_string(data_stream: &mut impl Write, s: &str) -> Result<()> {
+    let mut data = [b' '; STRING_LENGTH];
+    let bytes = s.as_bytes();
+    let len = bytes.len().min(STRING_LENGTH);
+    data[..len].copy_from_slice(&bytes[..len]);
+    data_stream.write_all(&data)?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ClientPacket {
+    Identification {
+        version: u8,
+        username: String,
+        cpe: bool,
+    },
+    SetBlock {
+        x: i16,
+        y: i16,
+        z: i16,
+        /// 0 destroyed, 1 placed
+        mode: u8,
+        block: u8,
+    },
+    /// we don't track players
+    Position,
+    Message(String),
+    ExtInfo {
+        app_name: String,
+        extension_count: i16,
+    },
+    ExtEntry {
+        name: String,
+        version: i32,
+    },
+    PluginMessage {
+        channel: u8,
+        data: [u8; PLUGIN_MESSAGE_LENGTH],
+    },
+}
+
+impl ClientPacket {
+    pub fn read(data_stream: &mut impl Read) -> Result<Self> {
+        Ok(match data_stream.read_u8()? {
+            IDENTIFICATION => {
+                let version = data_stream.read_u8()?;
+                let username = read_string(data_stream)?;
+                let _verification_key = read_string(data_stream)?;
+                let cpe = data_stream.read_u8()? == CPE_MAGIC;
+                Self::Identification {
+                    version,
+                    username,
+                    cpe,
+                }
+            }
+
+            SET_BLOCK_CLIENT => Self::SetBlock {
+                x: data_stream.read_i16::<NetworkEndian>()?,
+                y: data_stream.read_i16::<NetworkEndian>()?,
+                z: data_stream.read_i16::<NetworkEndian>()?,
+                mode: data_stream.read_u8()?,
+                block: data_stream.read_u8()?,
+            },
+
+            POSITION => {
+                // player id, x y z, yaw pitch
+                let mut data = [0; 1 + 3 * 2 + 2];
+                data_stream.read_exact(&mut data)?;
+                Self::Position
+            }
+
+            MESSAGE => {
+                let _unused = data_stream.read_u8()?;
+                Self::Message(read_string(data_stream)?)
+            }
+
+            EXT_INFO => Self::ExtInfo {
+                app_name: read_string(data_stream)?,
+                extension_count: data_stream.read_i16::<NetworkEndian>()?,
+            },
+
+            EXT_ENTRY => Self::ExtEntry {
+                name: read_string(data_stream)?,
+                version: data_stream.read_i32::<NetworkEndian>()?,
+            },
+
+            PLUGIN_MESSAGE => {
+                let channel = data_stream.read_u8()?;
+                let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+                data_stream.read_exact(&mut data)?;
+                Self::PluginMessage { channel, data }
+            }
+
+            // we don't announce any extension that adds more, and without a length
+            // there's no way to skip it
+            other => bail!("unexpected opcode {:#04x}", other),
+        })
+    }
+}
+
+pub fn write_server_identification(
+    data_stream: &mut impl Write,
+    name: &str,
+    motd: &str,
+) -> Result<()> {
+    data_stream.write_u8(IDENTIFICATION)?;
+    data_stream.write_u8(PROTOCOL_VERSION)?;
+    write_string(data_stream, name)?;
+    write_string(data_stream, motd)?;
+    // not op
+    data_stream.write_u8(0)?;
+    Ok(())
+}
+
+pub fn write_ping(data_stream: &mut impl Write) -> Result<()> {
+    data_stream.write_u8(PING)?;
+    Ok(())
+}
+
+/// `gzipped_blocks` as made by `map::Map::gzip`
+pub fn write_level(
+    data_stream: &mut impl Write,
+    gzipped_blocks: &[u8],
+    size: (i16, i16, i16),
+) -> Result<()> {
+    data_stream.write_u8(LEVEL_INITIALIZE)?;
+
+    let chunk_count = gzipped_blocks.len().div_ceil(LEVEL_CHUNK_LENGTH);
+    for (i, chunk) in gzipped_blocks.chunks(LEVEL_CHUNK_LENGTH).enumerate() {
+        let mut data = [0; LEVEL_CHUNK_LENGTH];
+        data[..chunk.len()].copy_from_slice(chunk);
+
+        data_stream.write_u8(LEVEL_DATA_CHUNK)?;
+        data_stream.write_i16::<NetworkEndian>(chunk.len().try_into()?)?;
+        data_stream.write_all(&data)?;
+        data_stream.write_u8(((i + 1) * 100 / chunk_count).try_into()?)?;
+    }
+
+    data_stream.write_u8(LEVEL_FINALIZE)?;
+    data_stream.write_i16::<NetworkEndian>(size.0)?;
+    data_stream.write_i16::<NetworkEndian>(size.1)?;
+    data_stream.write_i16::<NetworkEndian>(size.2)?;
+    Ok(())
+}
+
+pub fn write_set_block(
+    data_stream: &mut impl Write,
+    x: i16,
+    y: i16,
+    z: i16,
+    block: u8,
+) -> Result<()> {
+    data_stream.write_u8(SET_BLOCK)?;
+    data_stream.write_i16::<NetworkEndian>(x)?;
+    data_stream.write_i16::<NetworkEndian>(y)?;
+    data_stream.write_i16::<NetworkEndian>(z)?;
+    data_stream.write_u8(block)?;
+    Ok(())
+}
+
+/// `feet_pos` in blocks
+pub fn write_spawn_player(
+    data_stream: &mut impl Write,
+    entity_id: u8,
+    name: &str,
+    feet_pos: (f32, f32, f32),
+) -> Result<()> {
+    data_stream.write_u8(SPAWN_PLAYER)?;
+    data_stream.write_u8(entity_id)?;
+    write_string(data_stream, name)?;
+    // players are sent at eye height
+    let pos = (feet_pos.0, feet_pos.1 + EYE_HEIGHT, feet_pos.2);
+    for n in [pos.0, pos.1, pos.2] {
+        data_stream.write_i16::<NetworkEndian>((n * 32.0) as i16)?;
+    }
+    // yaw, pitch
+    data_stream.write_u8(0)?;
+    data_stream.write_u8(0)?;
+    Ok(())
+}
+
+pub fn write_message(data_stream: &mut impl Write, message: &str) -> Result<()> {
+    data_stream.write_u8(MESSAGE)?;
+    // player id, 0 for plain chat
+    data_stream.write_u8(0)?;
+    write_string(data_stream, message)?;
+    Ok(())
+}
+
+pub fn write_ext_info(
+    data_stream: &mut impl Write,
+    app_name: &str,
+    extensions: &[(&str, i32)],
+) -> Result<()> {
+    data_stream.write_u8(EXT_INFO)?;
+    write_string(data_stream, app_name)?;
+    data_stream.write_i16::<NetworkEndian>(extensions.len().try_into()?)?;
+    for &(name, version) in extensions {
+        data_stream.write_u8(EXT_ENTRY)?;
+        write_string(data_stream, name)?;
+        data_stream.write_i32::<NetworkEndian>(version)?;
+    }
+    Ok(())
+}
+
+/// at most `BULK_BLOCK_COUNT` of `(index, block)`
+pub fn write_bulk_block_update(data_stream: &mut impl Write, blocks: &[(i32, u8)]) -> Result<()> {
+    if blocks.is_empty() || blocks.len() > BULK_BLOCK_COUNT {
+        bail!("bulk update of {} blocks", blocks.len());
+    }
+
+    data_stream.write_u8(BULK_BLOCK_UPDATE)?;
+    data_stream.write_u8((blocks.len() - 1).try_into()?)?;
+    for i in 0..BULK_BLOCK_COUNT {
+        data_stream.write_i32::<NetworkEndian>(blocks.get(i).map_or(0, |&(index, _)| index))?;
+    }
+    for i in 0..BULK_BLOCK_COUNT {
+        data_stream.write_u8(blocks.get(i).map_or(0, |&(_, block)| block))?;
+    }
+    Ok(())
+}
+
+pub fn write_plugin_message(
+    data_stream: &mut impl Write,
+    channel: u8,
+    data: &[u8; PLUGIN_MESSAGE_LENGTH],
+) -> Result<()> {
+    data_stream.write_u8(PLUGIN_MESSAGE)?;
+    data_stream.write_u8(channel)?;
+    data_stream.write_all(data)?;
+    Ok(())
+}
+
+#[test]
+fn test_bulk_block_update() {
+    let mut data = Vec::new();
+    write_bulk_block_update(&mut data, &[(5, 1), (70000, 2)]).unwrap();
+    assert_eq!(data.len(), 1 + 1 + BULK_BLOCK_COUNT * 4 + BULK_BLOCK_COUNT);
+    assert_eq!(&data[..2], [BULK_BLOCK_UPDATE, 1]);
+    assert_eq!(&data[2..10], [0, 0, 0, 5, 0, 1, 0x11, 0x70]);
+    assert_eq!(&data[2 + BULK_BLOCK_COUNT * 4..][..3], [1, 2, 0]);
+
+    assert!(write_bulk_block_update(&mut Vec::new(), &[]).is_err());
+    assert!(write_bulk_block_update(&mut Vec::new(), &[(0, 1); BULK_BLOCK_COUNT + 1]).is_err());
+}
@@ -0,0 +1,372 @@
+use std::{
+    fs,
+    io::{BufReader, Write},
+    net::TcpStream,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+use toolgun_protocol::{
+    CoordEncoding, DEFAULT_CHANNEL, Fragment, Handshake, ImageData, LargeMessage, Message,
+    PLUGIN_MESSAGE_LENGTH, PROTOCOL_VERSION,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    classic::{
+        self, BULK_BLOCK_COUNT, BULK_BLOCK_UPDATE_EXT, ClientPacket, PLUGIN_MESSAGES_EXT, SELF_ID,
+    },
+    map::{AIR, Map},
+    script::Command,
+};
+
+pub const MAP_SIZE: (i16, i16, i16) = (64, 32, 64);
+pub const GROUND: i16 = 15;
+/// entity id of the other player the scripts fire from
+pub const BOT_ID: u8 = 1;
+/// how long scripts wait for the client's handshake before falling back to the
+/// legacy format
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+struct State {
+    map: Map,
+    supports_plugin_messages: bool,
+    supports_bulk_block_update: bool,
+    channel: u8,
+    /// the one we answered with, `None` until the client sent its own
+    handshake: Option<Handshake>,
+    next_message_id: u8,
+}
+
+/// One connected client, shared between the thread reading from it and the one
+/// running the script.
+pub struct Connection {
+    writer: Mutex<TcpStream>,
+    state: Mutex<State>,
+    handshake_received: Condvar,
+}
+
+impl Connection {
+    fn send(&self, f: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Result<()> {
+        // whole packets at once so threads can't interleave halves
+        let mut data = Vec::new();
+        f(&mut data)?;
+        self.writer.lock().unwrap().write_all(&data)?;
+        Ok(())
+    }
+
+    fn send_toolgun(&self, message: &Message) -> Result<()> {
+        let (channel, handshake) = {
+            let state = self.state.lock().unwrap();
+            if !state.supports_plugin_messages {
+                bail!("client doesn't support {}", PLUGIN_MESSAGES_EXT);
+            }
+            (state.channel, state.handshake)
+        };
+
+        let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+        message.encode(&mut data.as_mut_slice(), handshake)?;
+        self.send(|data_stream| classic::write_plugin_message(data_stream, channel, &data))
+    }
+
+    fn set_block(&self, x: i16, y: i16, z: i16, block: u8) -> Result<()> {
+        self.state.lock().unwrap().map.set(x, y, z, block);
+        self.send(|data_stream| classic::write_set_block(data_stream, x, y, z, block))
+    }
+
+    fn fill(&self, from: (i16, i16, i16), to: (i16, i16, i16), block: u8) -> Result<()> {
+        let (blocks, bulk) = {
+            let mut state = self.state.lock().unwrap();
+            let mut blocks = Vec::new();
+            for y in from.1.min(to.1)..=from.1.max(to.1) {
+                for z in from.2.min(to.2)..=from.2.max(to.2) {
+                    for x in from.0.min(to.0)..=from.0.max(to.0) {
+                        if state.map.contains(x, y, z) {
+                            state.map.set(x, y, z, block);
+                            blocks.push((x, y, z, state.map.index(x, y, z)));
+                        }
+                    }
+                }
+            }
+            (blocks, state.supports_bulk_block_update)
+        };
+
+        if bulk {
+            for chunk in blocks.chunks(BULK_BLOCK_COUNT) {
+                let chunk = chunk
+                    .iter()
+                    .map(|&(_, _, _, index)| (index, block))
+                    .collect::<Vec<_>>();
+                self.send(|data_stream| classic::write_bulk_block_update(data_stream, &chunk))?;
+            }
+        } else {
+            for (x, y, z, _) in blocks {
+                self.send(|data_stream| classic::write_set_block(data_stream, x, y, z, block))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send_texture(&self, png: Vec<u8>) -> Result<()> {
+        let (handshake, message_id) = {
+            let mut state = self.state.lock().unwrap();
+            let message_id = state.next_message_id;
+            state.next_message_id = message_id.wrapping_add(1);
+            (state.handshake, message_id)
+        };
+        if handshake.is_none() {
+            bail!("client never sent a handshake, it can't reassemble fragments");
+        }
+
+        let mut data = Vec::new();
+        LargeMessage::BeamTexture(ImageData::Png(png)).encode(&mut data)?;
+        for fragment in Fragment::split(message_id, &data)? {
+            self.send_toolgun(&Message::Fragment(fragment))?;
+        }
+        Ok(())
+    }
+
+    fn handle_plugin_message(&self, channel: u8, data: &[u8]) -> Result<()> {
+        let handshake = {
+            let state = self.state.lock().unwrap();
+            // the client may have been configured with another channel, its
+            // handshake says which
+            if channel != state.channel && !Handshake::is_handshake(data) {
+                debug!("ignoring plugin message on channel {}", channel);
+                return Ok(());
+            }
+            state.handshake
+        };
+
+        match Message::decode(data, handshake)? {
+            Message::Handshake(handshake) => {
+                info!("client handshake {:?}", handshake);
+                if !handshake.is_supported() {
+                    warn!(
+                        "ignoring handshake with unsupported protocol version {}",
+                        handshake.version
+                    );
+                    return Ok(());
+                }
+                // the one it came in on, unless the client has a preference
+                let channel = handshake.requested_channel().unwrap_or(channel);
+                let answer = Handshake {
+                    version: PROTOCOL_VERSION,
+                    coord_encoding: handshake.coord_encoding.min(CoordEncoding::I32),
+                    channel,
+                };
+                // on the client's channel, but before we start using opcodes
+                self.state.lock().unwrap().channel = answer.channel;
+                self.send_toolgun(&Message::Handshake(answer))?;
+
+                let mut state = self.state.lock().unwrap();
+                state.handshake = Some(answer);
+                self.handshake_received.notify_all();
+            }
+
+            Message::Packet(packet) => {
+                info!("client fired at {:?}", packet.target);
+            }
+
+            Message::Fragment(fragment) => {
+                info!(
+                    "client sent fragment {} of message {}",
+                    fragment.sequence, fragment.message_id
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_packet(&self, packet: ClientPacket) -> Result<()> {
+        match packet {
+            ClientPacket::SetBlock {
+                x,
+                y,
+                z,
+                mode,
+                block,
+            } => {
+                let block = if mode == 0 { AIR } else { block };
+                debug!(?x, ?y, ?z, ?block, "client set block");
+                // confirm it like a real server
+                self.set_block(x, y, z, block)?;
+            }
+
+            ClientPacket::PluginMessage { channel, data } => {
+                self.handle_plugin_message(channel, &data)?;
+            }
+
+            ClientPacket::Message(message) => {
+                info!("chat: {}", message);
+            }
+
+            ClientPacket::Position => {}
+
+            other => bail!("unexpected {:?} after login", other),
+        }
+        Ok(())
+    }
+
+    pub fn run_command(&self, command: &Command) -> Result<()> {
+        debug!(?command);
+        match command {
+            Command::Wait(duration) => thread::sleep(*duration),
+
+            Command::Packet { player_id, target } => {
+                self.send_toolgun(&Message::Packet(toolgun_protocol::Packet {
+                    player_id: *player_id,
+                    target: *target,
+                }))?;
+            }
+
+            Command::Set { x, y, z, block } => self.set_block(*x, *y, *z, *block)?,
+
+            Command::Fill { from, to, block } => self.fill(*from, *to, *block)?,
+
+            Command::Texture(path) => self.send_texture(fs::read(path)?)?,
+
+            Command::Say(message) => {
+                self.send(|data_stream| classic::write_message(data_stream, message))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Block until the client sent its handshake, `false` on timeout.
+    fn wait_for_handshake(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .handshake_received
+            .wait_timeout_while(state, HANDSHAKE_TIMEOUT, |state| state.handshake.is_none())
+            .unwrap();
+        state.handshake.is_some()
+    }
+}
+
+/// Log the client in, then run `script` against it.
+pub fn handle_client(stream: TcpStream, script: &[Command]) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let ClientPacket::Identification {
+        version,
+        username,
+        cpe,
+    } = ClientPacket::read(&mut reader)?
+    else {
+        bail!("expected PlayerIdentification");
+    };
+    info!(?version, ?username, ?cpe, "login");
+
+    let mut writer = stream;
+    let mut supports_plugin_messages = false;
+    let mut supports_bulk_block_update = false;
+    if cpe {
+        let mut data = Vec::new();
+        classic::write_ext_info(
+            &mut data,
+            env!("CARGO_PKG_NAME"),
+            &[(PLUGIN_MESSAGES_EXT, 1), (BULK_BLOCK_UPDATE_EXT, 1)],
+        )?;
+        writer.write_all(&data)?;
+
+        let ClientPacket::ExtInfo {
+            app_name,
+            extension_count,
+        } = ClientPacket::read(&mut reader)?
+        else {
+            bail!("expected ExtInfo");
+        };
+        info!(?app_name, ?extension_count);
+
+        for _ in 0..extension_count {
+            let ClientPacket::ExtEntry { name, version } = ClientPacket::read(&mut reader)? else {
+                bail!("expected ExtEntry");
+            };
+            debug!(?name, ?version);
+            match name.as_str() {
+                PLUGIN_MESSAGES_EXT => supports_plugin_messages = true,
+                BULK_BLOCK_UPDATE_EXT => supports_bulk_block_update = true,
+                _ => {}
+            }
+        }
+    }
+    if !supports_plugin_messages {
+        warn!(
+            "client doesn't support {}, toolgun packets won't be sent",
+            PLUGIN_MESSAGES_EXT
+        );
+    }
+
+    let map = Map::flat(MAP_SIZE.0, MAP_SIZE.1, MAP_SIZE.2, GROUND);
+    let mut data = Vec::new();
+    classic::write_server_identification(&mut data, "toolgun mock server", &username)?;
+    classic::write_level(&mut data, &map.gzip()?, map.size())?;
+    let center = (
+        f32::from(MAP_SIZE.0) / 2.0,
+        f32::from(GROUND + 1),
+        f32::from(MAP_SIZE.2) / 2.0,
+    );
+    classic::write_spawn_player(
+        &mut data,
+        SELF_ID,
+        &username,
+        (center.0, center.1, center.2 + 8.0),
+    )?;
+    classic::write_spawn_player(
+        &mut data,
+        BOT_ID,
+        "toolgun_bot",
+        (center.0, center.1, center.2 - 4.0),
+    )?;
+    writer.write_all(&data)?;
+
+    let connection = Arc::new(Connection {
+        writer: Mutex::new(writer),
+        state: Mutex::new(State {
+            map,
+            supports_plugin_messages,
+            supports_bulk_block_update,
+            channel: DEFAULT_CHANNEL,
+            handshake: None,
+            next_message_id: 0,
+        }),
+        handshake_received: Condvar::new(),
+    });
+
+    let reader_connection = connection.clone();
+    thread::spawn(move || {
+        loop {
+            let packet = match ClientPacket::read(&mut reader) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    info!("client gone: {:#}", e);
+                    break;
+                }
+            };
+            // a bad message is worth a look, not the end of the connection
+            if let Err(e) = reader_connection.handle_packet(packet) {
+                warn!("handling client packet: {:#}", e);
+            }
+        }
+    });
+
+    if supports_plugin_messages && !connection.wait_for_handshake() {
+        warn!("no handshake from the client, is the plugin loaded? using the legacy format");
+    }
+    for command in script {
+        if let Err(e) = connection.run_command(command) {
+            warn!("{:?}: {:#}", command, e);
+        }
+    }
+    info!("script finished");
+
+    // keep the client around until it leaves
+    loop {
+        thread::sleep(PING_INTERVAL);
+        connection.send(classic::write_ping)?;
+    }
+}
@@ -0,0 +1,74 @@
+mod classic;
+mod connection;
+mod map;
+mod script;
+
+use std::{env, fs, net::TcpListener, sync::Arc, thread};
+
+use anyhow::{Context, Result, bail};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+use self::script::parse_script;
+
+const DEFAULT_PORT: u16 = 25565;
+const DEMO_SCRIPT: &str = include_str!("../scenarios/demo.txt");
+
+const USAGE: &str = "usage: toolgun-mock-server [--port <port>] [script]
+
+Serves a flat map on localhost and runs `script` (the built-in demo by default)
+against every client that joins. Connect with ClassiCube using
+`mc://127.0.0.1:<port>/<name>/`.";
+
+fn main() -> Result<()> {
+    let my_crate_name = env!("CARGO_PKG_NAME").replace('-', "_");
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::from_default_env().add_directive(format!("{my_crate_name}=debug").parse()?),
+        )
+        .with_target(false)
+        .without_time()
+        .init();
+
+    let mut port = DEFAULT_PORT;
+    let mut script_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                port = args
+                    .next()
+                    .context("--port needs a value")?
+                    .parse()
+                    .context("bad --port")?;
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if script_path.is_none() => script_path = Some(arg),
+            _ => bail!("unexpected {:?}\n\n{}", arg, USAGE),
+        }
+    }
+
+    let script = match &script_path {
+        Some(path) => fs::read_to_string(path).with_context(|| format!("reading {path}"))?,
+        None => DEMO_SCRIPT.to_string(),
+    };
+    let script = Arc::new(parse_script(&script)?);
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    info!("listening on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let script = script.clone();
+        thread::spawn(move || {
+            let addr = stream.peer_addr().ok();
+            if let Err(e) = connection::handle_client(stream, &script) {
+                error!("{:?}: {:#}", addr, e);
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::io::Write;
+
+use anyhow::Result;
+use byteorder::{NetworkEndian, WriteBytesExt};
+use flate2::{Compression, write::GzEncoder};
+
+pub const AIR: u8 = 0;
+pub const STONE: u8 = 1;
+pub const GRASS: u8 = 2;
+pub const DIRT: u8 = 3;
+pub const BEDROCK: u8 = 7;
+
+pub struct Map {
+    pub width: i16,
+    pub height: i16,
+    pub length: i16,
+    blocks: Vec<u8>,
+}
+
+impl Map {
+    /// grass at `ground`, dirt and stone below, bedrock at the bottom
+    pub fn flat(width: i16, height: i16, length: i16, ground: i16) -> Self {
+        let mut map = Self {
+            width,
+            height,
+            length,
+            blocks: vec![AIR; width as usize * height as usize * length as usize],
+        };
+        for y in 0..=ground.min(height - 1) {
+            let block = match ground - y {
+                0 => GRASS,
+                1..=3 => DIRT,
+                _ if y == 0 => BEDROCK,
+                _ => STONE,
+            };
+            for z in 0..length {
+                for x in 0..width {
+                    map.set(x, y, z, block);
+                }
+            }
+        }
+        map
+    }
+
+    pub fn size(&self) -> (i16, i16, i16) {
+        (self.width, self.height, self.length)
+    }
+
+    pub fn contains(&self, x: i16, y: i16, z: i16) -> bool {
+        (0..self.width).contains(&x)
+            && (0..self.height).contains(&y)
+            && (0..self.length).contains(&z)
+    }
+
+    /// same order as the level data and `BulkBlockUpdate` indices
+    pub fn index(&self, x: i16, y: i16, z: i16) -> i32 {
+        (i32::from(y) * i32::from(self.length) + i32::from(z)) * i32::from(self.width)
+            + i32::from(x)
+    }
+
+    pub fn set(&mut self, x: i16, y: i16, z: i16, block: u8) {
+        if self.contains(x, y, z) {
+            let index = self.index(x, y, z) as usize;
+            self.blocks[index] = block;
+        }
+    }
+
+    /// block count then blocks, gzipped, as sent in `LevelDataChunk`s
+    pub fn gzip(&self) -> Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_i32::<NetworkEndian>(self.blocks.len().try_into()?)?;
+        encoder.write_all(&self.blocks)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+#[test]
+fn test_flat() {
+    use std::io::Read;
+
+    use byteorder::ReadBytesExt;
+    use flate2::read::GzDecoder;
+
+    let map = Map::flat(4, 8, 2, 5);
+    let gzipped = map.gzip().unwrap();
+    let mut decoder = GzDecoder::new(gzipped.as_slice());
+    assert_eq!(decoder.read_i32::<NetworkEndian>().unwrap(), 4 * 8 * 2);
+    let mut blocks = Vec::new();
+    decoder.read_to_end(&mut blocks).unwrap();
+
+    let column = (0..8)
+        .map(|y| blocks[map.index(3, y, 1) as usize])
+        .collect::<Vec<_>>();
+    assert_eq!(column, [BEDROCK, STONE, DIRT, DIRT, DIRT, GRASS, AIR, AIR]);
+}
@@ -0,0 +1,186 @@
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use toolgun_protocol::{BlockPos, Point, Target};
+
+/// One line of a scenario.
+///
+/// ```text
+/// # comment
+/// wait <millis>
+/// packet <player id> block <x> <y> <z>
+/// packet <player id> entity <entity id>
+/// packet <player id> point <x> <y> <z>
+/// set <x> <y> <z> <block>
+/// fill <x1> <y1> <z1> <x2> <y2> <z2> <block>
+/// texture <png path>
+/// say <message>
+/// ```
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Wait(Duration),
+    /// toolgun packet on our channel
+    Packet {
+        player_id: u8,
+        target: Target,
+    },
+    /// one classic `SetBlock`
+    Set {
+        x: i16,
+        y: i16,
+        z: i16,
+        block: u8,
+    },
+    /// cuboid sent as `BulkBlockUpdate`s, or `SetBlock`s without the extension
+    Fill {
+        from: (i16, i16, i16),
+        to: (i16, i16, i16),
+        block: u8,
+    },
+    /// beam texture, split into fragments
+    Texture(PathBuf),
+    Say(String),
+}
+
+fn parse<T>(words: &mut std::str::SplitWhitespace, what: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let Some(word) = words.next() else {
+        bail!("missing {}", what);
+    };
+    word.parse()
+        .with_context(|| format!("bad {} {:?}", what, word))
+}
+
+fn parse_xyz<T>(words: &mut std::str::SplitWhitespace) -> Result<(T, T, T)>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    Ok((parse(words, "x")?, parse(words, "y")?, parse(words, "z")?))
+}
+
+impl Command {
+    /// `None` for blank lines and comments
+    pub fn parse_line(line: &str) -> Result<Option<Self>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let mut words = line.split_whitespace();
+        let command = match words.next().unwrap_or_default() {
+            "wait" => Self::Wait(Duration::from_millis(parse(&mut words, "millis")?)),
+
+            "packet" => {
+                let player_id = parse(&mut words, "player id")?;
+                let target = match words.next() {
+                    Some("block") => {
+                        let (x, y, z) = parse_xyz(&mut words)?;
+                        Target::Block(BlockPos { x, y, z })
+                    }
+                    Some("entity") => Target::Entity(parse(&mut words, "entity id")?),
+                    Some("point") => {
+                        let (x, y, z) = parse_xyz(&mut words)?;
+                        Target::Point(Point { x, y, z })
+                    }
+                    other => bail!("unknown target {:?}", other),
+                };
+                Self::Packet { player_id, target }
+            }
+
+            "set" => {
+                let (x, y, z) = parse_xyz(&mut words)?;
+                Self::Set {
+                    x,
+                    y,
+                    z,
+                    block: parse(&mut words, "block")?,
+                }
+            }
+
+            "fill" => Self::Fill {
+                from: parse_xyz(&mut words)?,
+                to: parse_xyz(&mut words)?,
+                block: parse(&mut words, "block")?,
+            },
+
+            "texture" => Self::Texture(parse(&mut words, "path")?),
+
+            "say" => {
+                let message = line["say".len()..].trim().to_string();
+                return Ok(Some(Self::Say(message)));
+            }
+
+            other => bail!("unknown command {:?}", other),
+        };
+
+        if let Some(extra) = words.next() {
+            bail!("unexpected {:?}", extra);
+        }
+        Ok(Some(command))
+    }
+}
+
+pub fn parse_script(script: &str) -> Result<Vec<Command>> {
+    script
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            Command::parse_line(line)
+                .with_context(|| format!("line {}", i + 1))
+                .transpose()
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_script() {
+    let commands = parse_script(
+        "# hi\n\nwait 500\npacket 1 block 1 -2 3\npacket 1 entity 255\npacket 2 point 0.5 1 \
+         2\nset 1 2 3 4\nfill 0 0 0 2 2 2 1\ntexture beam.png\nsay hello  there\n",
+    )
+    .unwrap();
+    assert_eq!(
+        commands,
+        [
+            Command::Wait(Duration::from_millis(500)),
+            Command::Packet {
+                player_id: 1,
+                target: Target::Block(BlockPos { x: 1, y: -2, z: 3 }),
+            },
+            Command::Packet {
+                player_id: 1,
+                target: Target::Entity(255),
+            },
+            Command::Packet {
+                player_id: 2,
+                target: Target::Point(Point {
+                    x: 0.5,
+                    y: 1.0,
+                    z: 2.0,
+                }),
+            },
+            Command::Set {
+                x: 1,
+                y: 2,
+                z: 3,
+                block: 4,
+            },
+            Command::Fill {
+                from: (0, 0, 0),
+                to: (2, 2, 2),
+                block: 1,
+            },
+            Command::Texture("beam.png".into()),
+            Command::Say("hello  there".into()),
+        ]
+    );
+
+    assert!(parse_script("wait").is_err());
+    assert!(parse_script("set 1 2 3 4 5").is_err());
+    assert!(parse_script("packet 1 nowhere").is_err());
+    assert!(parse_script("jump").is_err());
+}
@@ -0,0 +1,285 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use tracing::warn;
+
+use crate::PLUGIN_MESSAGE_LENGTH;
+
+/// opcode, message id, sequence, total length
+const FRAGMENT_HEADER_LENGTH: usize = 1 + 1 + 1 + 2;
+pub const FRAGMENT_PAYLOAD_LENGTH: usize = PLUGIN_MESSAGE_LENGTH - FRAGMENT_HEADER_LENGTH;
+/// sequence is a `u8`
+pub const MAX_FRAGMENTS: usize = 256;
+pub const MAX_MESSAGE_LENGTH: usize = FRAGMENT_PAYLOAD_LENGTH * MAX_FRAGMENTS;
+
+/// give up on a message if its next fragment takes longer than this
+pub const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// messages being reassembled at once, so a server can't make us buffer
+/// `MAX_MESSAGE_LENGTH` for every message id
+const MAX_PARTIALS: usize = 4;
+
+/// One piece of a message too big for a single plugin message.
+///
+/// Plugin messages travel over TCP so fragments can't get lost or reordered by
+/// themselves, but fragments of different messages can interleave, and a server
+/// can stop halfway through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fragment {
+    pub message_id: u8,
+    pub sequence: u8,
+    pub total_length: u16,
+    pub payload: Vec<u8>,
+}
+
+fn fragment_count(total_length: usize) -> usize {
+    total_length.div_ceil(FRAGMENT_PAYLOAD_LENGTH).max(1)
+}
+
+/// how many payload bytes fragment `sequence` carries, `None` if it's past the end
+fn payload_length(total_length: usize, sequence: usize) -> Option<usize> {
+    if sequence >= fragment_count(total_length) {
+        return None;
+    }
+    Some((total_length - sequence * FRAGMENT_PAYLOAD_LENGTH).min(FRAGMENT_PAYLOAD_LENGTH))
+}
+
+impl Fragment {
+    /// expects the opcode to already be read
+    pub fn decode(data_stream: &mut impl Read) -> Result<Self> {
+        let message_id = data_stream.read_u8()?;
+        let sequence = data_stream.read_u8()?;
+        let total_length = data_stream.read_u16::<NetworkEndian>()?;
+        if usize::from(total_length) > MAX_MESSAGE_LENGTH {
+            bail!("message too big: {} bytes", total_length);
+        }
+        let Some(payload_length) = payload_length(total_length.into(), sequence.into()) else {
+            bail!(
+                "fragment {} past the end of {} bytes",
+                sequence,
+                total_length
+            );
+        };
+
+        // bounded by FRAGMENT_PAYLOAD_LENGTH, not by anything the server sends
+        let mut payload = vec![0; payload_length];
+        data_stream.read_exact(&mut payload)?;
+
+        Ok(Self {
+            message_id,
+            sequence,
+            total_length,
+            payload,
+        })
+    }
+
+    /// how many fragments the whole message takes, this one included
+    pub fn count(&self) -> usize {
+        fragment_count(self.total_length.into())
+    }
+
+    /// without the opcode
+    pub fn encode(&self, data_stream: &mut impl Write) -> Result<()> {
+        data_stream.write_u8(self.message_id)?;
+        data_stream.write_u8(self.sequence)?;
+        data_stream.write_u16::<NetworkEndian>(self.total_length)?;
+        data_stream.write_all(&self.payload)?;
+        Ok(())
+    }
+
+    pub fn split(message_id: u8, data: &[u8]) -> Result<Vec<Self>> {
+        if data.len() > MAX_MESSAGE_LENGTH {
+            bail!("message too big: {} bytes", data.len());
+        }
+        let total_length = data.len().try_into()?;
+
+        if data.is_empty() {
+            return Ok(vec![Self {
+                message_id,
+                sequence: 0,
+                total_length,
+                payload: Vec::new(),
+            }]);
+        }
+
+        data.chunks(FRAGMENT_PAYLOAD_LENGTH)
+            .enumerate()
+            .map(|(sequence, payload)| {
+                Ok(Self {
+                    message_id,
+                    sequence: sequence.try_into()?,
+                    total_length,
+                    payload: payload.to_vec(),
+                })
+            })
+            .collect()
+    }
+}
+
+struct Partial {
+    total_length: u16,
+    data: Vec<u8>,
+    next_sequence: usize,
+    last_received: Instant,
+}
+
+#[derive(Default)]
+pub struct Reassembler {
+    partials: HashMap<u8, Partial>,
+}
+
+impl Reassembler {
+    /// Returns the whole message once its last fragment arrives.
+    pub fn receive(&mut self, fragment: Fragment, now: Instant) -> Result<Option<Vec<u8>>> {
+        let Fragment {
+            message_id,
+            sequence,
+            total_length,
+            payload,
+        } = fragment;
+
+        if sequence == 0 {
+            if self.partials.remove(&message_id).is_some() {
+                warn!("message {} restarted before it finished", message_id);
+            }
+            if self.partials.len() >= MAX_PARTIALS
+                && let Some(oldest) = self
+                    .partials
+                    .iter()
+                    .min_by_key(|(_, partial)| partial.last_received)
+                    .map(|(&id, _)| id)
+            {
+                warn!("too many messages at once, dropping message {}", oldest);
+                self.partials.remove(&oldest);
+            }
+            self.partials.insert(
+                message_id,
+                Partial {
+                    total_length,
+                    data: Vec::with_capacity(total_length.into()),
+                    next_sequence: 0,
+                    last_received: now,
+                },
+            );
+        }
+
+        let Some(partial) = self.partials.get_mut(&message_id) else {
+            bail!("fragment {} of unknown message {}", sequence, message_id);
+        };
+        if partial.total_length != total_length || partial.next_sequence != sequence.into() {
+            self.partials.remove(&message_id);
+            bail!(
+                "fragment {} of message {} out of order, dropping it",
+                sequence,
+                message_id
+            );
+        }
+
+        partial.data.extend_from_slice(&payload);
+        partial.next_sequence += 1;
+        partial.last_received = now;
+
+        if partial.next_sequence == fragment_count(total_length.into()) {
+            Ok(self
+                .partials
+                .remove(&message_id)
+                .map(|partial| partial.data))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// drop messages the server stopped sending
+    pub fn expire(&mut self, now: Instant) {
+        self.partials.retain(|message_id, partial| {
+            let alive = now.saturating_duration_since(partial.last_received) < FRAGMENT_TIMEOUT;
+            if !alive {
+                warn!("message {} timed out", message_id);
+            }
+            alive
+        });
+    }
+}
+
+#[test]
+fn test_fragment_roundtrip() {
+    use std::io::Cursor;
+
+    let now = Instant::now();
+    for length in [0, 1, FRAGMENT_PAYLOAD_LENGTH, 1000, MAX_MESSAGE_LENGTH] {
+        let data = (0..length).map(|i| i as u8).collect::<Vec<_>>();
+        let fragments = Fragment::split(7, &data).unwrap();
+        assert_eq!(fragments.len(), fragment_count(length));
+        assert!(
+            fragments
+                .iter()
+                .all(|fragment| fragment.count() == fragments.len())
+        );
+
+        let mut reassembler = Reassembler::default();
+        let mut result = None;
+        for fragment in fragments {
+            let mut encoded = Vec::new();
+            fragment.encode(&mut encoded).unwrap();
+            // leaves room for the opcode
+            assert!(encoded.len() < PLUGIN_MESSAGE_LENGTH);
+
+            let fragment = Fragment::decode(&mut Cursor::new(&encoded)).unwrap();
+            assert!(result.is_none());
+            result = reassembler.receive(fragment, now).unwrap();
+        }
+        assert_eq!(result, Some(data));
+    }
+
+    assert!(Fragment::split(0, &vec![0; MAX_MESSAGE_LENGTH + 1]).is_err());
+}
+
+#[test]
+fn test_reassembler_errors() {
+    let now = Instant::now();
+    let data = vec![1; FRAGMENT_PAYLOAD_LENGTH * 3];
+    let fragments = Fragment::split(1, &data).unwrap();
+
+    // missing the start
+    let mut reassembler = Reassembler::default();
+    assert!(reassembler.receive(fragments[1].clone(), now).is_err());
+
+    // skipped a fragment
+    let mut reassembler = Reassembler::default();
+    assert!(
+        reassembler
+            .receive(fragments[0].clone(), now)
+            .unwrap()
+            .is_none()
+    );
+    assert!(reassembler.receive(fragments[2].clone(), now).is_err());
+
+    // interleaved with another message
+    let other = Fragment::split(2, &[2; 10]).unwrap();
+    let mut reassembler = Reassembler::default();
+    assert!(
+        reassembler
+            .receive(fragments[0].clone(), now)
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(
+        reassembler.receive(other[0].clone(), now).unwrap(),
+        Some(vec![2; 10])
+    );
+    assert!(
+        reassembler
+            .receive(fragments[1].clone(), now)
+            .unwrap()
+            .is_none()
+    );
+
+    // timed out
+    reassembler.expire(now + FRAGMENT_TIMEOUT);
+    assert!(reassembler.receive(fragments[2].clone(), now).is_err());
+}
@@ -0,0 +1,138 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use crate::{
+    DEFAULT_CHANNEL, PLUGIN_MESSAGE_LENGTH,
+    large_message::LargeMessage,
+    packet::{CoordEncoding, HANDSHAKE_MAGIC, Handshake, Message, PROTOCOL_VERSION},
+};
+
+// everything on our channel comes straight from the server, so throw arbitrary
+// bytes at the decoders
+
+const ITERATIONS: usize = 10_000;
+
+/// Generous enough for an `anyhow::Error` with a captured backtrace, but catches
+/// anything sized by an untrusted length field.
+const MAX_DECODE_ALLOCATION: usize = 64 * 1024;
+
+thread_local!(
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+);
+
+/// Counts bytes allocated per thread, tests run in parallel.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // `try_with` because thread locals may already be gone during thread exit
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + layout.size()));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// total bytes allocated while running `f`
+fn allocated_during<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATED.get();
+    let result = f();
+    (result, ALLOCATED.get() - before)
+}
+
+/// xorshift, so a failing input can be reproduced from the iteration number
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn fill(&mut self, data: &mut [u8]) {
+        for chunk in data.chunks_mut(8) {
+            let bytes = self.next().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+fn arbitrary_message(rng: &mut Rng, i: usize) -> [u8; PLUGIN_MESSAGE_LENGTH] {
+    let mut data = [0; PLUGIN_MESSAGE_LENGTH];
+    rng.fill(&mut data);
+
+    // fully random bytes almost never get past the first check, so steer some
+    // of them into the interesting branches
+    match i % 4 {
+        1 => data[..HANDSHAKE_MAGIC.len()].copy_from_slice(&HANDSHAKE_MAGIC),
+        2 => {
+            // opcode, then the target kind after the player id
+            data[0] %= 2;
+            data[2] %= 4;
+        }
+        _ => {}
+    }
+    data
+}
+
+#[test]
+fn test_fuzz_decode() {
+    let handshakes = [
+        None,
+        Some(Handshake {
+            version: PROTOCOL_VERSION,
+            coord_encoding: CoordEncoding::U16,
+            channel: DEFAULT_CHANNEL,
+        }),
+        Some(Handshake {
+            version: PROTOCOL_VERSION,
+            coord_encoding: CoordEncoding::I32,
+            channel: DEFAULT_CHANNEL,
+        }),
+    ];
+
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    for i in 0..ITERATIONS {
+        let data = arbitrary_message(&mut rng, i);
+        for handshake in handshakes {
+            // every prefix too, in case something assumes the full 64 bytes
+            for len in [0, 1, 7, PLUGIN_MESSAGE_LENGTH] {
+                let (_, allocated) = allocated_during(|| Message::decode(&data[..len], handshake));
+                assert!(
+                    allocated <= MAX_DECODE_ALLOCATION,
+                    "iteration {i}: {allocated} bytes for {:?}",
+                    &data[..len]
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_fuzz_decode_large() {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    for i in 0..ITERATIONS {
+        let mut data = vec![0; i % 256];
+        rng.fill(&mut data);
+        // steer towards a beam texture of either format
+        if let [kind, format, ..] = data.as_mut_slice() {
+            *kind %= 2;
+            *format %= 3;
+        }
+
+        let (_, allocated) = allocated_during(|| LargeMessage::decode(&data));
+        assert!(
+            allocated <= MAX_DECODE_ALLOCATION,
+            "iteration {i}: {allocated} bytes for {data:?}"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::io::{Cursor, Write};
+
+use anyhow::{Result, bail};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+
+const BEAM_TEXTURE_KIND: u8 = 0;
+
+const PNG_FORMAT: u8 = 0;
+const RGBA_FORMAT: u8 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ImageData {
+    Png(Vec<u8>),
+    /// 4 bytes per pixel, rows top to bottom
+    Rgba {
+        width: u16,
+        height: u16,
+        pixels: Vec<u8>,
+    },
+}
+
+/// Messages too big for one plugin message, reassembled from `Fragment`s.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LargeMessage {
+    /// the server's own look for every beam from now on
+    BeamTexture(ImageData),
+}
+
+impl LargeMessage {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut data_stream = Cursor::new(data);
+        Ok(match data_stream.read_u8()? {
+            BEAM_TEXTURE_KIND => Self::BeamTexture(match data_stream.read_u8()? {
+                PNG_FORMAT => ImageData::Png(data[2..].to_vec()),
+                RGBA_FORMAT => {
+                    let width = data_stream.read_u16::<NetworkEndian>()?;
+                    let height = data_stream.read_u16::<NetworkEndian>()?;
+                    let pixels = &data[6..];
+                    let expected_length = usize::from(width) * usize::from(height) * 4;
+                    if pixels.len() != expected_length {
+                        bail!(
+                            "{}x{} rgba needs {} bytes, got {}",
+                            width,
+                            height,
+                            expected_length,
+                            pixels.len()
+                        );
+                    }
+                    ImageData::Rgba {
+                        width,
+                        height,
+                        pixels: pixels.to_vec(),
+                    }
+                }
+                other => bail!("unknown image format {}", other),
+            }),
+            other => bail!("unknown large message kind {}", other),
+        })
+    }
+
+    pub fn encode(&self, data_stream: &mut impl Write) -> Result<()> {
+        match self {
+            Self::BeamTexture(image) => {
+                data_stream.write_u8(BEAM_TEXTURE_KIND)?;
+                match image {
+                    ImageData::Png(png) => {
+                        data_stream.write_u8(PNG_FORMAT)?;
+                        data_stream.write_all(png)?;
+                    }
+                    ImageData::Rgba {
+                        width,
+                        height,
+                        pixels,
+                    } => {
+                        data_stream.write_u8(RGBA_FORMAT)?;
+                        data_stream.write_u16::<NetworkEndian>(*width)?;
+                        data_stream.write_u16::<NetworkEndian>(*height)?;
+                        data_stream.write_all(pixels)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_large_message() {
+    for message in [
+        LargeMessage::BeamTexture(ImageData::Png(vec![1, 2, 3])),
+        LargeMessage::BeamTexture(ImageData::Rgba {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 0, 255, 0, 128],
+        }),
+    ] {
+        let mut data = Vec::new();
+        message.encode(&mut data).unwrap();
+        assert_eq!(LargeMessage::decode(&data).unwrap(), message);
+    }
+
+    // pixel count doesn't match the size
+    assert!(LargeMessage::decode(&[BEAM_TEXTURE_KIND, RGBA_FORMAT, 0, 2, 0, 2, 1, 2, 3]).is_err());
+    assert!(LargeMessage::decode(&[]).is_err());
+}
@@ -0,0 +1,22 @@
+//! Wire format of the toolgun plugin messages, shared by the ClassiCube plugin
+//! and anything on the server side that wants to talk to it.
+
+pub mod fragment;
+#[cfg(test)]
+mod fuzz;
+pub mod large_message;
+pub mod packet;
+
+pub use self::{
+    fragment::{Fragment, Reassembler},
+    large_message::{ImageData, LargeMessage},
+    packet::{
+        BlockPos, CoordEncoding, HANDSHAKE_MAGIC, Handshake, Message, PROTOCOL_VERSION, Packet,
+        Point, Target,
+    },
+};
+
+/// CPE PluginMessages channel used unless the server moves us elsewhere
+pub const DEFAULT_CHANNEL: u8 = 71;
+/// every plugin message is exactly this long, padded with zeroes
+pub const PLUGIN_MESSAGE_LENGTH: usize = 64;
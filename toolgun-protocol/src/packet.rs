@@ -0,0 +1,491 @@
+use std::io::{Cursor, Read, Write};
+
+use anyhow::{Result, bail};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::fragment::Fragment;
+
+/// prefix that marks a handshake so it can't be confused with a legacy `Packet`
+pub const HANDSHAKE_MAGIC: [u8; 4] = *b"TGUN";
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// `Target::Point` coordinates are in 1/32 block units, like classic entity positions
+pub const FIXED_POINT_SCALE: f32 = 32.0;
+
+/// How block coordinates are written on the wire.
+///
+/// Ordered from oldest to newest, so the client can announce the newest one it
+/// understands and the server picks any encoding up to that.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CoordEncoding {
+    /// unsigned 16-bit, the original format
+    #[default]
+    U16,
+    /// signed 32-bit, for negative positions and maps bigger than 65535 blocks
+    /// (CPE ExtEntityPositions)
+    I32,
+}
+
+impl CoordEncoding {
+    pub fn from_u8(n: u8) -> Result<Self> {
+        Ok(match n {
+            0 => Self::U16,
+            1 => Self::I32,
+            other => bail!("unknown coord encoding {}", other),
+        })
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Self::U16 => 0,
+            Self::I32 => 1,
+        }
+    }
+
+    pub fn read(self, data_stream: &mut impl Read) -> Result<i32> {
+        Ok(match self {
+            Self::U16 => data_stream.read_u16::<NetworkEndian>()?.into(),
+            Self::I32 => data_stream.read_i32::<NetworkEndian>()?,
+        })
+    }
+
+    pub fn write(self, data_stream: &mut impl Write, n: i32) -> Result<()> {
+        match self {
+            Self::U16 => data_stream.write_u16::<NetworkEndian>(n.try_into()?)?,
+            Self::I32 => data_stream.write_i32::<NetworkEndian>(n)?,
+        }
+        Ok(())
+    }
+}
+
+/// Sent by the client on map load with the newest `CoordEncoding` it supports
+/// and the channel it's listening on, and answered by the server with the
+/// encoding and channel it will use from then on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Handshake {
+    pub version: u8,
+    pub coord_encoding: CoordEncoding,
+    /// lets the server move us off the default channel if something else uses it,
+    /// 0 for no preference
+    pub channel: u8,
+}
+
+impl Handshake {
+    pub fn is_handshake(data: &[u8]) -> bool {
+        data.starts_with(&HANDSHAKE_MAGIC)
+    }
+
+    /// Whether we understand the format `version` stands for.
+    pub fn is_supported(&self) -> bool {
+        self.version == PROTOCOL_VERSION
+    }
+
+    /// The channel asked for, `None` for no preference.
+    pub fn requested_channel(&self) -> Option<u8> {
+        (self.channel != 0).then_some(self.channel)
+    }
+
+    pub fn decode(data_stream: &mut impl Read) -> Result<Self> {
+        let mut magic = [0; HANDSHAKE_MAGIC.len()];
+        data_stream.read_exact(&mut magic)?;
+        if magic != HANDSHAKE_MAGIC {
+            bail!("bad handshake magic {:?}", magic);
+        }
+
+        let version = data_stream.read_u8()?;
+        let coord_encoding = CoordEncoding::from_u8(data_stream.read_u8()?)?;
+        let channel = data_stream.read_u8()?;
+
+        Ok(Self {
+            version,
+            coord_encoding,
+            channel,
+        })
+    }
+
+    pub fn encode(&self, data_stream: &mut impl Write) -> Result<()> {
+        data_stream.write_all(&HANDSHAKE_MAGIC)?;
+        data_stream.write_u8(self.version)?;
+        data_stream.write_u8(self.coord_encoding.to_u8())?;
+        data_stream.write_u8(self.channel)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// in blocks
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// Where a laser ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Target {
+    /// center of a block
+    Block(BlockPos),
+    /// follows an entity around for as long as the laser lives
+    Entity(u8),
+    /// an exact point, e.g. where a block face was hit
+    Point(Point),
+}
+
+impl Target {
+    pub fn decode(data_stream: &mut impl Read, coord_encoding: CoordEncoding) -> Result<Self> {
+        Ok(match data_stream.read_u8()? {
+            0 => Self::Block(BlockPos {
+                x: coord_encoding.read(data_stream)?,
+                y: coord_encoding.read(data_stream)?,
+                z: coord_encoding.read(data_stream)?,
+            }),
+            1 => Self::Entity(data_stream.read_u8()?),
+            2 => Self::Point(Point {
+                x: coord_encoding.read(data_stream)? as f32 / FIXED_POINT_SCALE,
+                y: coord_encoding.read(data_stream)? as f32 / FIXED_POINT_SCALE,
+                z: coord_encoding.read(data_stream)? as f32 / FIXED_POINT_SCALE,
+            }),
+            other => bail!("unknown target kind {}", other),
+        })
+    }
+
+    pub fn encode(
+        &self,
+        data_stream: &mut impl Write,
+        coord_encoding: CoordEncoding,
+    ) -> Result<()> {
+        match *self {
+            Self::Block(block_pos) => {
+                data_stream.write_u8(0)?;
+                coord_encoding.write(data_stream, block_pos.x)?;
+                coord_encoding.write(data_stream, block_pos.y)?;
+                coord_encoding.write(data_stream, block_pos.z)?;
+            }
+            Self::Entity(entity_id) => {
+                data_stream.write_u8(1)?;
+                data_stream.write_u8(entity_id)?;
+            }
+            Self::Point(pos) => {
+                data_stream.write_u8(2)?;
+                for n in [pos.x, pos.y, pos.z] {
+                    coord_encoding.write(data_stream, (n * FIXED_POINT_SCALE).round() as i32)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Packet {
+    pub player_id: u8,
+    pub target: Target,
+}
+
+impl Packet {
+    /// `handshake` is `None` until the server has answered ours, in which case the
+    /// legacy format is used: always a block target with `CoordEncoding::U16`.
+    pub fn decode(data_stream: &mut impl Read, handshake: Option<Handshake>) -> Result<Self> {
+        let player_id = data_stream.read_u8()?;
+        let target = match handshake {
+            Some(handshake) => Target::decode(data_stream, handshake.coord_encoding)?,
+            None => Target::Block(BlockPos {
+                x: CoordEncoding::U16.read(data_stream)?,
+                y: CoordEncoding::U16.read(data_stream)?,
+                z: CoordEncoding::U16.read(data_stream)?,
+            }),
+        };
+
+        Ok(Self { player_id, target })
+    }
+
+    pub fn encode(&self, data_stream: &mut impl Write, handshake: Option<Handshake>) -> Result<()> {
+        data_stream.write_u8(self.player_id)?;
+        match (handshake, self.target) {
+            (Some(handshake), target) => target.encode(data_stream, handshake.coord_encoding)?,
+            (None, Target::Block(block_pos)) => {
+                CoordEncoding::U16.write(data_stream, block_pos.x)?;
+                CoordEncoding::U16.write(data_stream, block_pos.y)?;
+                CoordEncoding::U16.write(data_stream, block_pos.z)?;
+            }
+            (None, target) => bail!("{:?} needs a handshake", target),
+        }
+        Ok(())
+    }
+}
+
+const PACKET_OPCODE: u8 = 0;
+const FRAGMENT_OPCODE: u8 = 1;
+
+/// Everything that can be sent on the channel.
+///
+/// Once the handshake is done every message but the handshake itself starts with
+/// an opcode; before that only legacy `Packet`s exist.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Message {
+    Handshake(Handshake),
+    Packet(Packet),
+    Fragment(Fragment),
+}
+
+impl Message {
+    /// `handshake` is the one the server answered with, if any.
+    ///
+    /// Before that a legacy `Packet` can start with `HANDSHAKE_MAGIC` too, from
+    /// player 0x54 at x 0x4755, so it's only a handshake if it's one we support.
+    /// One that also carries a supported version and encoding is still taken for
+    /// a handshake. Afterwards the magic can't be mistaken for an opcode.
+    pub fn decode(data: &[u8], handshake: Option<Handshake>) -> Result<Self> {
+        let mut data_stream = Cursor::new(data);
+        if handshake.is_none() {
+            if Handshake::is_handshake(data)
+                && let Ok(handshake) = Handshake::decode(&mut Cursor::new(data))
+                && handshake.is_supported()
+            {
+                return Ok(Self::Handshake(handshake));
+            }
+            return Ok(Self::Packet(Packet::decode(&mut data_stream, None)?));
+        }
+        if Handshake::is_handshake(data) {
+            return Ok(Self::Handshake(Handshake::decode(&mut data_stream)?));
+        }
+
+        Ok(match data_stream.read_u8()? {
+            PACKET_OPCODE => Self::Packet(Packet::decode(&mut data_stream, handshake)?),
+            FRAGMENT_OPCODE => Self::Fragment(Fragment::decode(&mut data_stream)?),
+            other => bail!("unknown opcode {}", other),
+        })
+    }
+
+    pub fn encode(&self, data_stream: &mut impl Write, handshake: Option<Handshake>) -> Result<()> {
+        match self {
+            Self::Handshake(handshake) => handshake.encode(data_stream)?,
+
+            Self::Packet(packet) => {
+                if handshake.is_some() {
+                    data_stream.write_u8(PACKET_OPCODE)?;
+                }
+                packet.encode(data_stream, handshake)?;
+            }
+
+            Self::Fragment(fragment) => {
+                if handshake.is_none() {
+                    bail!("fragments need a handshake");
+                }
+                data_stream.write_u8(FRAGMENT_OPCODE)?;
+                fragment.encode(data_stream)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+const I32_HANDSHAKE: Option<Handshake> = Some(Handshake {
+    version: PROTOCOL_VERSION,
+    coord_encoding: CoordEncoding::I32,
+    channel: crate::DEFAULT_CHANNEL,
+});
+
+#[test]
+fn test_decode_u16() {
+    let data: [u8; 7] = [7, 0xFF, 0xFF, 0x00, 0x40, 0x12, 0x34];
+    let packet = Packet::decode(&mut Cursor::new(&data), None).unwrap();
+    assert_eq!(packet.player_id, 7);
+    let Target::Block(block_pos) = packet.target else {
+        panic!("{:?}", packet.target);
+    };
+    assert_eq!(block_pos.x, 65535);
+    assert_eq!(block_pos.y, 64);
+    assert_eq!(block_pos.z, 0x1234);
+}
+
+#[test]
+fn test_decode_i32() {
+    for (x, y, z) in [
+        (-1i32, -64i32, -100_000i32),
+        (65536, 1_000_000, i32::MAX),
+        (i32::MIN, 0, 12),
+    ] {
+        let mut data = vec![3, 0];
+        data.extend(x.to_be_bytes());
+        data.extend(y.to_be_bytes());
+        data.extend(z.to_be_bytes());
+
+        let packet = Packet::decode(&mut Cursor::new(&data), I32_HANDSHAKE).unwrap();
+        assert_eq!(packet.player_id, 3);
+        let Target::Block(block_pos) = packet.target else {
+            panic!("{:?}", packet.target);
+        };
+        assert_eq!(block_pos.x, x);
+        assert_eq!(block_pos.y, y);
+        assert_eq!(block_pos.z, z);
+
+        let mut encoded = Vec::new();
+        packet.encode(&mut encoded, I32_HANDSHAKE).unwrap();
+        assert_eq!(encoded, data);
+    }
+}
+
+#[test]
+fn test_encode_u16_out_of_range() {
+    for n in [-1, 65536] {
+        let packet = Packet {
+            player_id: 0,
+            target: Target::Block(BlockPos { x: n, y: 0, z: 0 }),
+        };
+        assert!(packet.encode(&mut Vec::new(), None).is_err());
+    }
+}
+
+#[test]
+fn test_decode_targets() {
+    let data: [u8; 3] = [1, 1, 42];
+    let packet = Packet::decode(&mut Cursor::new(&data), I32_HANDSHAKE).unwrap();
+    assert!(matches!(packet.target, Target::Entity(42)));
+
+    let mut data = vec![1, 2];
+    data.extend(16i32.to_be_bytes());
+    data.extend((-8i32).to_be_bytes());
+    data.extend((64 * 32 + 1i32).to_be_bytes());
+    let packet = Packet::decode(&mut Cursor::new(&data), I32_HANDSHAKE).unwrap();
+    let Target::Point(pos) = packet.target else {
+        panic!("{:?}", packet.target);
+    };
+    assert_eq!(pos.x, 0.5);
+    assert_eq!(pos.y, -0.25);
+    assert_eq!(pos.z, 64.03125);
+
+    let mut encoded = Vec::new();
+    packet.encode(&mut encoded, I32_HANDSHAKE).unwrap();
+    assert_eq!(encoded, data);
+
+    // only block targets exist in the legacy format
+    assert!(packet.encode(&mut Vec::new(), None).is_err());
+}
+
+#[test]
+fn test_handshake() {
+    let handshake = Handshake {
+        version: PROTOCOL_VERSION,
+        coord_encoding: CoordEncoding::I32,
+        channel: 200,
+    };
+    let mut data = Vec::new();
+    handshake.encode(&mut data).unwrap();
+    assert!(Handshake::is_handshake(&data));
+    assert_eq!(
+        Handshake::decode(&mut Cursor::new(&data)).unwrap(),
+        handshake
+    );
+
+    assert!(handshake.is_supported());
+    assert_eq!(handshake.requested_channel(), Some(200));
+
+    for version in [0, PROTOCOL_VERSION + 1] {
+        assert!(
+            !Handshake {
+                version,
+                ..handshake
+            }
+            .is_supported()
+        );
+    }
+    assert_eq!(
+        Handshake {
+            channel: 0,
+            ..handshake
+        }
+        .requested_channel(),
+        None
+    );
+
+    // a legacy packet is never a handshake
+    assert!(!Handshake::is_handshake(&[7, 0, 1, 0, 2, 0, 3]));
+
+    // unless it happens to start with the magic
+    let colliding = [0x54, 0x47, 0x55, 0x4E, 0x00, 0x12, 0x34];
+    assert!(Handshake::is_handshake(&colliding));
+    let Message::Packet(packet) = Message::decode(&colliding, None).unwrap() else {
+        panic!();
+    };
+    assert_eq!(packet.player_id, 0x54);
+    assert_eq!(
+        packet.target,
+        Target::Block(BlockPos {
+            x: 0x4755,
+            y: 0x4E00,
+            z: 0x1234,
+        })
+    );
+    assert!(matches!(
+        Message::decode(&data, None).unwrap(),
+        Message::Handshake(decoded) if decoded == handshake
+    ));
+    assert!(matches!(
+        Message::decode(&data, Some(handshake)).unwrap(),
+        Message::Handshake(decoded) if decoded == handshake
+    ));
+}
+
+#[test]
+fn test_message_opcodes() {
+    let packet = Message::Packet(Packet {
+        player_id: 9,
+        target: Target::Entity(4),
+    });
+    let mut data = Vec::new();
+    packet.encode(&mut data, I32_HANDSHAKE).unwrap();
+    assert_eq!(data, [PACKET_OPCODE, 9, 1, 4]);
+    assert!(matches!(
+        Message::decode(&data, I32_HANDSHAKE).unwrap(),
+        Message::Packet(Packet {
+            player_id: 9,
+            target: Target::Entity(4),
+        })
+    ));
+
+    let fragment = Fragment::split(3, &[1, 2, 3]).unwrap().remove(0);
+    let mut data = Vec::new();
+    Message::Fragment(fragment.clone())
+        .encode(&mut data, I32_HANDSHAKE)
+        .unwrap();
+    assert_eq!(data[0], FRAGMENT_OPCODE);
+    let Message::Fragment(decoded) = Message::decode(&data, I32_HANDSHAKE).unwrap() else {
+        panic!();
+    };
+    assert_eq!(decoded, fragment);
+
+    // legacy servers never see opcodes
+    assert!(
+        Message::Fragment(fragment)
+            .encode(&mut Vec::new(), None)
+            .is_err()
+    );
+    assert!(
+        Message::decode(&[FRAGMENT_OPCODE; 7], None).is_ok_and(|message| matches!(
+            message,
+            Message::Packet(Packet {
+                player_id: FRAGMENT_OPCODE,
+                ..
+            })
+        ))
+    );
+}